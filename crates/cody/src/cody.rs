@@ -0,0 +1,4221 @@
+mod cody_settings;
+pub mod request;
+
+pub use cody_settings::{CodyCompletionDisplay, CodyContextMode, CodyDebounceMode, CodySettings};
+
+use anyhow::{anyhow, Context as _, Result};
+use async_compression::futures::bufread::GzipDecoder;
+use async_tar::Archive;
+use collections::{HashMap, HashSet, VecDeque};
+use command_palette_hooks::CommandPaletteFilter;
+use db::kvp::KEY_VALUE_STORE;
+use futures::{
+    channel::oneshot,
+    future::{BoxFuture, Shared},
+    AsyncReadExt, AsyncWriteExt, Future, FutureExt, TryFutureExt,
+};
+use gpui::{
+    actions, impl_actions, AppContext, AsyncAppContext, Context, Entity, EntityId, EventEmitter,
+    Global, Model, ModelContext, Task, WeakModel,
+};
+use language::{
+    language_settings::{all_language_settings, language_settings},
+    point_from_lsp, point_to_lsp, Anchor, Bias, Buffer, BufferSnapshot, Language,
+    LanguageServerName, LineEnding, OffsetRangeExt, PointUtf16, ToPointUtf16,
+};
+use lsp::request::Request as _;
+use lsp::{LanguageServer, LanguageServerBinary, LanguageServerId};
+use node_runtime::NodeRuntime;
+use parking_lot::Mutex;
+use request::StatusNotification;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsStore};
+use sha2::{Digest, Sha256};
+use smol::{fs, io::BufReader, stream::StreamExt};
+use std::{
+    any::TypeId,
+    ffi::OsString,
+    mem,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use util::{
+    fs::remove_matching,
+    github::{github_release_by_tag, latest_github_release},
+    http::{HttpClient, StatusCode},
+    http_proxy_from_env,
+    maybe, paths, ResultExt,
+};
+
+/// The only endpoint that supports the `SignInInitiate` device flow; every other endpoint is
+/// assumed to be an Enterprise instance authenticated with a personal access token instead.
+pub const CODY_AUTH_URL: &str = "https://sourcegraph.com";
+
+/// Key under which `Cody::persist_state` stores the last-used endpoint and known accounts, so
+/// `Cody::start` can restore them on the next launch instead of always coming up pointed at
+/// `CODY_AUTH_URL`.
+const CODY_PERSISTED_STATE_KEY: &str = "cody-runtime-state";
+
+#[derive(Serialize, Deserialize)]
+struct PersistedCodyState {
+    current_endpoint: String,
+    known_accounts: Vec<String>,
+}
+
+/// Interval between `SignInConfirm` polls while waiting for the user to finish the device flow
+/// in their browser, doubling on each failed attempt up to `SIGN_IN_CONFIRM_MAX_BACKOFF`.
+const SIGN_IN_CONFIRM_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const SIGN_IN_CONFIRM_MAX_BACKOFF: Duration = Duration::from_secs(10);
+/// Give up polling after this long without the user completing the device flow.
+const SIGN_IN_CONFIRM_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Bounds on retrying `start_language_server` when enabling Cody, for transient failures like a
+/// network blip while fetching the agent binary.
+const START_LANGUAGE_SERVER_MAX_ATTEMPTS: u32 = 3;
+const START_LANGUAGE_SERVER_INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const START_LANGUAGE_SERVER_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The number of most-recently-edited buffers tracked as `cody.context.include_open_files`
+/// candidates.
+const RECENTLY_EDITED_BUFFERS_LIMIT: usize = 10;
+
+/// Cooldown before the first retry after the agent rate-limits a completion request, doubling on
+/// each consecutive rate limit up to `RATE_LIMIT_COOLDOWN_MAX`.
+const RATE_LIMIT_COOLDOWN_INITIAL: Duration = Duration::from_secs(30);
+const RATE_LIMIT_COOLDOWN_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// Agent releases at or above this version report completion acceptance via
+/// `request::NotifyAcceptedV2`'s `autocomplete/completions/accepted` method instead of the older
+/// `notifyAccepted`.
+const ACCEPT_COMPLETION_METHOD_V2_MIN_VERSION: semver::Version = semver::Version::new(6, 0, 0);
+
+/// The chat conversation id used for `Cody::explain`, kept separate from the main chat panel's
+/// so explanations don't pile up in the user's regular chat history.
+const EXPLAIN_SELECTION_CHAT_ID: &str = "zed-cody-explain";
+
+/// The chat conversation id used for `Cody::generate_tests`, kept separate from the main chat
+/// panel's and from `EXPLAIN_SELECTION_CHAT_ID` so generated tests don't pile up alongside other
+/// one-off requests.
+const GENERATE_TESTS_CHAT_ID: &str = "zed-cody-generate-tests";
+
+/// Model ids the agent is known to accept for `cody.model`, used only to decide whether to log a
+/// warning about a likely-misspelled setting -- an unrecognized name is still forwarded as-is,
+/// since new models can roll out on the agent side before Zed knows their names.
+const KNOWN_CODY_MODELS: &[&str] = &[
+    "anthropic::claude-3-opus",
+    "anthropic::claude-3-sonnet",
+    "anthropic::claude-3-haiku",
+    "openai::gpt-4o",
+    "fireworks::starcoder",
+];
+
+actions!(
+    cody,
+    [
+        Suggest,
+        NextSuggestion,
+        PreviousSuggestion,
+        EditSelection,
+        ExplainSelection,
+        GenerateTests,
+        OpenChat,
+        Reinstall,
+        Restart,
+        SignIn,
+        CancelSignIn,
+        SignOut,
+        ToggleCodyForBuffer
+    ]
+);
+
+/// Switches the active Sourcegraph endpoint to one already known to `Cody::known_accounts`,
+/// reusing whatever access token was previously stored for it.
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct SwitchAccount {
+    pub endpoint: String,
+}
+
+impl_actions!(cody, [SwitchAccount]);
+
+pub fn init(
+    new_server_id: LanguageServerId,
+    http: Arc<dyn HttpClient>,
+    node_runtime: Arc<dyn NodeRuntime>,
+    cx: &mut AppContext,
+) {
+    CodySettings::register(cx);
+
+    let cody = cx.new_model({
+        let node_runtime = node_runtime.clone();
+        move |cx| Cody::start(new_server_id, http, node_runtime, cx)
+    });
+    Cody::set_global(cody.clone(), cx);
+    cx.observe(&cody, |handle, cx| {
+        let cody_action_types = [
+            TypeId::of::<Suggest>(),
+            TypeId::of::<NextSuggestion>(),
+            TypeId::of::<PreviousSuggestion>(),
+            TypeId::of::<EditSelection>(),
+            TypeId::of::<ExplainSelection>(),
+            TypeId::of::<GenerateTests>(),
+            TypeId::of::<OpenChat>(),
+            TypeId::of::<Reinstall>(),
+            TypeId::of::<Restart>(),
+            TypeId::of::<SwitchAccount>(),
+        ];
+        let cody_auth_action_types = [TypeId::of::<SignOut>()];
+        let cody_no_auth_action_types =
+            [TypeId::of::<SignIn>(), TypeId::of::<CancelSignIn>()];
+        let status = handle.read(cx).status();
+        let filter = CommandPaletteFilter::global_mut(cx);
+
+        match status {
+            Status::Disabled => {
+                filter.hide_action_types(&cody_action_types);
+                filter.hide_action_types(&cody_auth_action_types);
+                filter.hide_action_types(&cody_no_auth_action_types);
+            }
+            Status::Authorized => {
+                filter.hide_action_types(&cody_no_auth_action_types);
+                filter.show_action_types(cody_action_types.iter().chain(&cody_auth_action_types));
+            }
+            _ => {
+                filter.hide_action_types(&cody_action_types);
+                filter.hide_action_types(&cody_auth_action_types);
+                filter.show_action_types(cody_no_auth_action_types.iter());
+            }
+        }
+    })
+    .detach();
+
+    cx.on_action(|_: &SignIn, cx| {
+        if let Some(cody) = Cody::global(cx) {
+            cody.update(cx, |cody, cx| cody.sign_in(cx))
+                .detach_and_log_err(cx);
+        }
+    });
+    cx.on_action(|_: &CancelSignIn, cx| {
+        if let Some(cody) = Cody::global(cx) {
+            cody.update(cx, |cody, cx| cody.cancel_sign_in(cx));
+        }
+    });
+    cx.on_action(|_: &SignOut, cx| {
+        if let Some(cody) = Cody::global(cx) {
+            cody.update(cx, |cody, cx| cody.sign_out(cx))
+                .detach_and_log_err(cx);
+        }
+    });
+    cx.on_action(|_: &Reinstall, cx| {
+        if let Some(cody) = Cody::global(cx) {
+            cody.update(cx, |cody, cx| cody.reinstall(cx)).detach();
+        }
+    });
+    cx.on_action(|_: &Restart, cx| {
+        if let Some(cody) = Cody::global(cx) {
+            cody.update(cx, |cody, cx| cody.restart(cx)).detach();
+        }
+    });
+    cx.on_action(|action: &SwitchAccount, cx| {
+        if let Some(cody) = Cody::global(cx) {
+            cody.update(cx, |cody, cx| cody.switch_account(action.endpoint.clone(), cx))
+                .detach();
+        }
+    });
+}
+
+enum CodyServer {
+    Disabled,
+    Starting { task: Shared<Task<()>> },
+    Error(Arc<str>),
+    Running(RunningCodyServer),
+    /// The agent process was shut down after `cody.idle_timeout` of inactivity. Any subsequent
+    /// `register_buffer` or completion request starts a fresh agent, the same way enabling the
+    /// `cody.enabled` setting does.
+    Suspended,
+}
+
+impl CodyServer {
+    fn as_authenticated(&mut self) -> Result<&mut RunningCodyServer> {
+        let server = self.as_running()?;
+        if matches!(server.sign_in_status, SignInStatus::Authorized { .. }) {
+            Ok(server)
+        } else {
+            Err(anyhow!("must sign in before using cody"))
+        }
+    }
+
+    fn as_running(&mut self) -> Result<&mut RunningCodyServer> {
+        match self {
+            CodyServer::Starting { .. } => Err(anyhow!("cody is still starting")),
+            CodyServer::Disabled => Err(anyhow!("cody is disabled")),
+            CodyServer::Suspended => Err(anyhow!("cody is suspended because it was idle")),
+            CodyServer::Error(error) => {
+                Err(anyhow!("cody was not started because of an error: {}", error))
+            }
+            CodyServer::Running(server) => Ok(server),
+        }
+    }
+}
+
+struct RunningCodyServer {
+    name: LanguageServerName,
+    lsp: Arc<LanguageServer>,
+    sign_in_status: SignInStatus,
+    /// The signed-in account's username, as last reported to `update_sign_in_status`.
+    /// `None` before the first successful sign-in, or once signed out.
+    username: Option<String>,
+    registered_buffers: HashMap<EntityId, RegisteredBuffer>,
+    /// Chained after each new buffer's `DidOpenTextDocument` so registering many buffers at once
+    /// (e.g. opening a large project) sends them one at a time through the background executor
+    /// instead of all synchronously back to back.
+    pending_opens: Shared<Task<()>>,
+    activity: Option<Arc<str>>,
+    supports_incremental_sync: bool,
+    /// Whether the agent reported a version new enough to use `request::NotifyAcceptedV2`
+    /// instead of the legacy `request::NotifyAccepted`.
+    accept_completion_method_v2: bool,
+    completion_cache: CompletionCache,
+    /// Outstanding `NotifyAccepted`/`NotifyPartialAccept`/`NotifyRejected` requests, tagged with
+    /// the id that was handed out for them in `next_telemetry_id`. Kept around so
+    /// `update_sign_in_status` can drop (and thereby cancel) every one of them on sign-out --
+    /// without this, a completion accepted right before signing out could still reach the agent
+    /// after a different account has signed in on the same session. Each entry also removes
+    /// itself once its request resolves on its own (see `track_telemetry_request`), so a long
+    /// session with many completions doesn't grow this unboundedly.
+    pending_telemetry: Vec<(u64, Task<()>)>,
+    next_telemetry_id: u64,
+}
+
+#[derive(Clone, Debug)]
+enum SignInStatus {
+    Authorized {
+        /// `false` when the agent reported `request::SignInStatus::MaybeOk`, meaning it could
+        /// only verify the token's shape offline. `verify_sign_in_status` re-checks this against
+        /// the network in the background and downgrades the status if it comes back negative.
+        verified: bool,
+    },
+    Unauthorized,
+    SigningIn {
+        prompt: Option<request::PromptUserDeviceFlow>,
+        task: Shared<Task<Result<(), Arc<anyhow::Error>>>>,
+    },
+    SignedOut,
+}
+
+#[derive(Debug, Clone)]
+pub enum Status {
+    Starting {
+        task: Shared<Task<()>>,
+    },
+    /// The agent release is being downloaded. `percent` is `None` when the download's
+    /// `Content-Length` isn't known, in which case a consumer should render an indeterminate
+    /// progress indicator instead of a determinate one.
+    Downloading {
+        percent: Option<u8>,
+    },
+    Error(Arc<str>),
+    Disabled,
+    Suspended,
+    SignedOut,
+    SigningIn {
+        prompt: Option<request::PromptUserDeviceFlow>,
+    },
+    Unauthorized,
+    Authorized,
+    /// Provisionally authorized: the agent could only verify the access token's shape offline
+    /// (`request::SignInStatus::MaybeOk`), not that it's still valid, and a background check
+    /// against the network is pending. Not considered authorized by `is_authorized` until that
+    /// check confirms it, so features gated on full access stay disabled until then.
+    Unverified,
+}
+
+impl Status {
+    pub fn is_authorized(&self) -> bool {
+        matches!(self, Status::Authorized)
+    }
+}
+
+/// Progress of an in-flight `get_cody_lsp` download, reported through `Cody::download_progress`
+/// while `status()` is `CodyServer::Starting`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DownloadProgress {
+    Determinate(u8),
+    Indeterminate,
+}
+
+struct RegisteredBuffer {
+    uri: lsp::Url,
+    language_id: String,
+    snapshot: BufferSnapshot,
+    snapshot_version: i32,
+    _subscriptions: [gpui::Subscription; 2],
+    pending_buffer_change: Task<Option<()>>,
+    /// The number of `completions`/`completions_cycling` requests for this buffer currently in
+    /// flight. A count rather than a flag, since cycling can overlap an initial request.
+    pending_completions: usize,
+    /// The completions last returned for this buffer, kept around so UI code (and tests) can ask
+    /// what's currently showing without re-requesting.
+    last_completions: Vec<Completion>,
+    /// Whether `ToggleCodyForBuffer` has silenced completions for this buffer. In-memory only —
+    /// it resets the next time the buffer is registered (e.g. after being closed and reopened),
+    /// unlike `cody.enabled`, which is a persistent setting.
+    muted: bool,
+}
+
+impl RegisteredBuffer {
+    fn report_changes(
+        &mut self,
+        buffer: &Model<Buffer>,
+        cx: &mut ModelContext<Cody>,
+    ) -> oneshot::Receiver<(i32, BufferSnapshot)> {
+        let (done_tx, done_rx) = oneshot::channel();
+
+        if buffer.read(cx).version() == self.snapshot.version {
+            // Still wait for any queued buffer-open to flush first, so a completion requested
+            // right after registering a buffer doesn't reach the agent before its own
+            // `textDocument/didOpen` does.
+            let snapshot_version = self.snapshot_version;
+            let snapshot = self.snapshot.clone();
+            let prev_pending_change =
+                mem::replace(&mut self.pending_buffer_change, Task::ready(None));
+            self.pending_buffer_change = cx.background_executor().spawn(async move {
+                prev_pending_change.await;
+                let _ = done_tx.send((snapshot_version, snapshot));
+                Some(())
+            });
+        } else {
+            let buffer = buffer.downgrade();
+            let id = buffer.entity_id();
+            let prev_pending_change =
+                mem::replace(&mut self.pending_buffer_change, Task::ready(None));
+            self.pending_buffer_change = cx.spawn(move |cody, mut cx| async move {
+                prev_pending_change.await;
+
+                const REPORT_CHANGES_DEBOUNCE: Duration = Duration::from_millis(75);
+                cx.background_executor().timer(REPORT_CHANGES_DEBOUNCE).await;
+
+                let old_version = cody
+                    .update(&mut cx, |cody, _| {
+                        let server = cody.server.as_authenticated().log_err()?;
+                        let buffer = server.registered_buffers.get_mut(&id)?;
+                        Some(buffer.snapshot.version.clone())
+                    })
+                    .ok()??;
+                let new_snapshot = buffer.update(&mut cx, |buffer, _| buffer.snapshot()).ok()?;
+
+                let content_changes = cx
+                    .background_executor()
+                    .spawn({
+                        let new_snapshot = new_snapshot.clone();
+                        async move {
+                            new_snapshot
+                                .edits_since::<(PointUtf16, usize)>(&old_version)
+                                .map(|edit| {
+                                    let edit_start = edit.new.start.0;
+                                    let edit_end = edit_start + (edit.old.end.0 - edit.old.start.0);
+                                    let new_text = new_snapshot
+                                        .text_for_range(edit.new.start.1..edit.new.end.1)
+                                        .collect();
+                                    lsp::TextDocumentContentChangeEvent {
+                                        range: Some(lsp::Range::new(
+                                            point_to_lsp(edit_start),
+                                            point_to_lsp(edit_end),
+                                        )),
+                                        range_length: None,
+                                        text: new_text,
+                                    }
+                                })
+                                .collect::<Vec<_>>()
+                        }
+                    })
+                    .await;
+
+                cody.update(&mut cx, |cody, _| {
+                    let server = cody.server.as_authenticated().log_err()?;
+                    let buffer = server.registered_buffers.get_mut(&id)?;
+                    if !content_changes.is_empty() {
+                        buffer.snapshot_version += 1;
+                        server.completion_cache.invalidate_uri(&buffer.uri);
+                        let content_changes = if server.supports_incremental_sync {
+                            content_changes
+                        } else {
+                            vec![lsp::TextDocumentContentChangeEvent {
+                                range: None,
+                                range_length: None,
+                                text: new_snapshot.text(),
+                            }]
+                        };
+                        buffer.snapshot = new_snapshot;
+                        server
+                            .lsp
+                            .notify::<lsp::notification::DidChangeTextDocument>(
+                                lsp::DidChangeTextDocumentParams {
+                                    text_document: lsp::VersionedTextDocumentIdentifier::new(
+                                        buffer.uri.clone(),
+                                        buffer.snapshot_version,
+                                    ),
+                                    content_changes,
+                                },
+                            )
+                            .log_err();
+                    }
+                    let _ = done_tx.send((buffer.snapshot_version, buffer.snapshot.clone()));
+                    Some(())
+                })
+                .ok()?;
+
+                Some(())
+            });
+        }
+
+        done_rx
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub uuid: String,
+    pub range: Range<Anchor>,
+    pub text: String,
+    /// Whether `text` is a snippet (e.g. containing `$0`-style tabstops) that should be expanded
+    /// through the editor's snippet machinery on acceptance, rather than inserted as plain text.
+    pub is_snippet: bool,
+    /// When this completion was requested from the agent, so `accept_completion` can report how
+    /// long it took the user to accept it. Never sent to the agent.
+    pub requested_at: Instant,
+}
+
+/// A small LRU cache of completion results, keyed by the request that produced them so an
+/// unchanged `(uri, position, version)` tuple (e.g. re-opening the completions menu) can be
+/// served without round-tripping to the agent.
+#[derive(Default)]
+struct CompletionCache {
+    capacity: usize,
+    order: Vec<(lsp::Url, lsp::Position, i32)>,
+    entries: HashMap<(lsp::Url, lsp::Position, i32), Vec<Completion>>,
+}
+
+impl CompletionCache {
+    fn get(&mut self, key: &(lsp::Url, lsp::Position, i32)) -> Option<Vec<Completion>> {
+        let completions = self.entries.get(key)?.clone();
+        self.order.retain(|k| k != key);
+        self.order.push(key.clone());
+        Some(completions)
+    }
+
+    fn put(&mut self, key: (lsp::Url, lsp::Position, i32), completions: Vec<Completion>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.insert(key.clone(), completions).is_none() {
+            self.order.push(key);
+        }
+        while self.order.len() > self.capacity {
+            let oldest = self.order.remove(0);
+            self.entries.remove(&oldest);
+        }
+    }
+
+    /// Drops every cached completion for `uri`, called whenever that buffer's version changes
+    /// so a stale completion for an old version of the text can never be served.
+    fn invalidate_uri(&mut self, uri: &lsp::Url) {
+        self.entries.retain(|(entry_uri, _, _), _| entry_uri != uri);
+        self.order.retain(|(entry_uri, _, _)| entry_uri != uri);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+pub struct Cody {
+    http: Arc<dyn HttpClient>,
+    node_runtime: Arc<dyn NodeRuntime>,
+    server: CodyServer,
+    buffers: HashSet<WeakModel<Buffer>>,
+    /// Buffers that have been edited, most-recently-edited first, capped at
+    /// `RECENTLY_EDITED_BUFFERS_LIMIT`. Used as the candidate pool for `cody.context.include_open_files`.
+    recently_edited_buffers: VecDeque<WeakModel<Buffer>>,
+    server_id: LanguageServerId,
+    /// The Sourcegraph instance completions and chat are currently routed to.
+    current_endpoint: String,
+    /// Every endpoint `set_endpoint` has been called with, for the account-switcher menu. Saved
+    /// along with `current_endpoint` by `persist_state` and restored by `restore_persisted_state`.
+    known_accounts: Vec<String>,
+    /// Cancelled and restarted on every `register_buffer`/completion request; suspends the agent
+    /// via `suspend_for_idle` if it's ever allowed to run to completion.
+    idle_shutdown_task: Task<()>,
+    /// Set by `enter_rate_limit_cooldown` to when completions stop being suppressed after the
+    /// agent returned a rate-limit (429) error, cleared automatically once the cooldown elapses.
+    /// A deadline rather than the cooldown's `Duration`, so `rate_limited_for` can report
+    /// accurate remaining time no matter when it's called during the cooldown.
+    rate_limited_until: Option<Instant>,
+    /// The cooldown `enter_rate_limit_cooldown` will use the next time it's called, doubling (up
+    /// to `RATE_LIMIT_COOLDOWN_MAX`) each consecutive time a completion is rate-limited, and reset
+    /// to `RATE_LIMIT_COOLDOWN_INITIAL` the next time a completion succeeds.
+    rate_limit_backoff: Duration,
+    rate_limit_cooldown_task: Task<()>,
+    /// The most recent agent log/status messages, oldest first, capped at `MAX_LOG_ENTRIES` for
+    /// the "Show Cody Logs" view. Not persisted.
+    log_entries: VecDeque<CodyLogEntry>,
+    /// Set while `get_cody_lsp` is downloading an agent release, cleared once it resolves either
+    /// way. Surfaced as `Status::Downloading` so `status()` reflects an in-progress download
+    /// instead of the generic `Status::Starting`.
+    download_progress: Option<DownloadProgress>,
+    /// The text of the most recent `Status::Error`, kept around after the `CodyErrorToast`
+    /// notification that surfaced it is dismissed, so it can still be copied for a bug report via
+    /// `last_error`. Cleared the next time the agent starts successfully.
+    last_error: Option<Arc<str>>,
+    /// Enterprise remote repositories chat requests should be scoped to, set by a custom UI via
+    /// `set_selected_repos` (e.g. the chat panel's repo picker, which persists the selection per
+    /// workspace). Empty means chat uses the agent's own default scope.
+    selected_repos: Vec<request::Repo>,
+    _subscription: gpui::Subscription,
+}
+
+/// One line surfaced to the "Show Cody Logs" view, either a `LogMessage` notification from the
+/// agent or a human-readable rendering of a `StatusNotification`.
+#[derive(Debug, Clone)]
+pub struct CodyLogEntry {
+    pub level: log::Level,
+    pub message: String,
+}
+
+/// How many `CodyLogEntry`s are kept for the "Show Cody Logs" view before the oldest are dropped.
+const MAX_LOG_ENTRIES: usize = 1000;
+
+pub enum Event {
+    CodyLanguageServerStarted,
+    /// Emitted synchronously from `request_completions`, before the request is sent to the
+    /// agent. Followed by one `CompletionsReceived` for the same request only when it resolves
+    /// with completions; none follows when the server isn't signed in, the buffer is muted, the
+    /// request fails, or it is dropped before resolving.
+    CompletionsRequested,
+    /// Emitted once the completions for a prior `CompletionsRequested` are available, whether
+    /// they came from the completion cache or a round trip to the agent.
+    CompletionsReceived { count: usize },
+    /// Emitted each time the agent streams in more of a longer completion, ahead of the
+    /// `CompletionsReceived` for the same request. `text` is the full accumulated text so far.
+    CompletionChunkReceived { uuid: String, text: String },
+    /// Emitted from `accept_completion` for the completion the user accepted. `latency` is how
+    /// long it took from requesting the completion to the user accepting it, for tuning
+    /// debounce/context settings.
+    CompletionAccepted { uuid: String, latency: Duration },
+    /// Emitted from `update_sign_in_status` whenever the agent's sign-in state changes, so
+    /// consumers that only care about auth (like a dedicated Cody view) can `cx.subscribe`
+    /// instead of `cx.observe`-ing the whole model.
+    SignInStatusChanged(Status),
+    /// Emitted from `enter_rate_limit_cooldown` when the agent rate-limits a completion request.
+    RateLimited { cooldown: Duration },
+    /// Emitted whenever a new entry is appended to `Cody::log_entries`, so the "Show Cody Logs"
+    /// view can append to a live log instead of only showing a point-in-time snapshot.
+    LogMessage(CodyLogEntry),
+    /// Emitted from `handle_agent_crash` when a request fails because the agent process exited
+    /// unexpectedly, just before it's automatically restarted.
+    AgentCrashed,
+}
+
+impl EventEmitter<Event> for Cody {}
+
+struct GlobalCody(Model<Cody>);
+
+impl Global for GlobalCody {}
+
+impl Cody {
+    pub fn global(cx: &AppContext) -> Option<Model<Self>> {
+        cx.try_global::<GlobalCody>().map(|model| model.0.clone())
+    }
+
+    pub fn set_global(cody: Model<Self>, cx: &mut AppContext) {
+        cx.set_global(GlobalCody(cody));
+    }
+
+    fn start(
+        new_server_id: LanguageServerId,
+        http: Arc<dyn HttpClient>,
+        node_runtime: Arc<dyn NodeRuntime>,
+        cx: &mut ModelContext<Self>,
+    ) -> Self {
+        let mut this = Self {
+            server_id: new_server_id,
+            http,
+            node_runtime,
+            server: CodyServer::Disabled,
+            buffers: Default::default(),
+            recently_edited_buffers: Default::default(),
+            current_endpoint: CODY_AUTH_URL.to_string(),
+            known_accounts: vec![CODY_AUTH_URL.to_string()],
+            idle_shutdown_task: Task::ready(()),
+            rate_limited_until: None,
+            rate_limit_backoff: RATE_LIMIT_COOLDOWN_INITIAL,
+            rate_limit_cooldown_task: Task::ready(()),
+            log_entries: Default::default(),
+            download_progress: None,
+            last_error: None,
+            selected_repos: Vec::new(),
+            _subscription: cx.on_app_quit(Self::shutdown_language_server),
+        };
+        let restore_persisted_state = this.restore_persisted_state(cx);
+        cx.spawn(|this, mut cx| async move {
+            restore_persisted_state.await;
+            this.update(&mut cx, |this, cx| this.enable_or_disable_cody(cx)).ok();
+        })
+        .detach();
+        cx.observe_global::<SettingsStore>(move |this, cx| this.enable_or_disable_cody(cx))
+            .detach();
+        this
+    }
+
+    /// Restores the endpoint and known accounts saved by the last `persist_state` call, so
+    /// switching endpoints or signing into additional accounts survives a Zed restart instead of
+    /// always coming back up pointed at `CODY_AUTH_URL`. The returned task resolves once
+    /// `current_endpoint` reflects any persisted state -- `start` waits for it before the initial
+    /// `enable_or_disable_cody`, since that call captures `current_endpoint` at the moment it
+    /// runs; starting it first would hit `CODY_AUTH_URL` (sending its handshake to the public
+    /// instance) for every Enterprise user, only to restart moments later once this resolves.
+    fn restore_persisted_state(&self, cx: &mut ModelContext<Self>) -> Task<()> {
+        cx.spawn(|this, mut cx| async move {
+            let state = cx
+                .background_executor()
+                .spawn(async move { KEY_VALUE_STORE.read_kvp(CODY_PERSISTED_STATE_KEY) })
+                .await
+                .log_err()
+                .flatten()
+                .and_then(|state| serde_json::from_str::<PersistedCodyState>(&state).log_err());
+            let Some(state) = state else { return };
+            this.update(&mut cx, |this, cx| {
+                this.known_accounts = state.known_accounts;
+                this.current_endpoint = state.current_endpoint;
+                if !matches!(this.server, CodyServer::Disabled) {
+                    this.restart(cx).detach();
+                }
+            })
+            .ok();
+        })
+    }
+
+    /// Saves the current endpoint and known accounts so the next `Cody::start` can restore them
+    /// via `restore_persisted_state`.
+    fn persist_state(&self, cx: &AppContext) -> Task<Result<()>> {
+        let state = PersistedCodyState {
+            current_endpoint: self.current_endpoint.clone(),
+            known_accounts: self.known_accounts.clone(),
+        };
+        cx.background_executor().spawn(async move {
+            let state = serde_json::to_string(&state)?;
+            KEY_VALUE_STORE
+                .write_kvp(CODY_PERSISTED_STATE_KEY.to_string(), state)
+                .await
+        })
+    }
+
+    fn shutdown_language_server(
+        &mut self,
+        _cx: &mut ModelContext<Self>,
+    ) -> impl Future<Output = ()> {
+        let shutdown = match mem::replace(&mut self.server, CodyServer::Disabled) {
+            CodyServer::Running(server) => Some(Box::pin(async move { server.lsp.shutdown() })),
+            _ => None,
+        };
+
+        async move {
+            if let Some(shutdown) = shutdown {
+                shutdown.await;
+            }
+        }
+    }
+
+    fn enable_or_disable_cody(&mut self, cx: &mut ModelContext<Self>) {
+        let server_id = self.server_id;
+        let http = self.http.clone();
+        let node_runtime = self.node_runtime.clone();
+        let endpoint = self.current_endpoint.clone();
+        if CodySettings::get_global(cx).enabled {
+            if matches!(self.server, CodyServer::Disabled | CodyServer::Suspended) {
+                let start_task = cx
+                    .spawn(move |this, mut cx| async move {
+                        let mut attempt = 1;
+                        let mut backoff = START_LANGUAGE_SERVER_INITIAL_BACKOFF;
+                        loop {
+                            Self::start_language_server(
+                                server_id,
+                                http.clone(),
+                                node_runtime.clone(),
+                                endpoint.clone(),
+                                attempt,
+                                START_LANGUAGE_SERVER_MAX_ATTEMPTS,
+                                this.clone(),
+                                cx.clone(),
+                            )
+                            .await;
+
+                            let started = this
+                                .update(&mut cx, |this, _| {
+                                    !matches!(
+                                        this.server,
+                                        CodyServer::Error(_) | CodyServer::Starting { .. }
+                                    )
+                                })
+                                .unwrap_or(true);
+                            if started || attempt >= START_LANGUAGE_SERVER_MAX_ATTEMPTS {
+                                break;
+                            }
+
+                            cx.background_executor().timer(backoff).await;
+                            backoff = (backoff * 2).min(START_LANGUAGE_SERVER_MAX_BACKOFF);
+                            attempt += 1;
+                        }
+                    })
+                    .shared();
+                self.server = CodyServer::Starting { task: start_task };
+                cx.notify();
+            }
+        } else {
+            self.server = CodyServer::Disabled;
+            cx.notify();
+        }
+    }
+
+    /// Restarts the idle-shutdown countdown. Called on every `register_buffer` and completion
+    /// request, so the agent only suspends after `cody.idle_timeout` has elapsed with no activity
+    /// at all, not just since it was started.
+    fn reset_idle_timer(&mut self, cx: &mut ModelContext<Self>) {
+        let Some(idle_timeout) = CodySettings::get_global(cx).idle_timeout else {
+            self.idle_shutdown_task = Task::ready(());
+            return;
+        };
+        let idle_timeout = Duration::from_secs(idle_timeout);
+        self.idle_shutdown_task = cx.spawn(|this, mut cx| async move {
+            cx.background_executor().timer(idle_timeout).await;
+            this.update(&mut cx, |this, cx| this.suspend_for_idle(cx)).ok();
+        });
+    }
+
+    /// Shuts down the agent process after `cody.idle_timeout` of inactivity, leaving `self.server`
+    /// in `CodyServer::Suspended` so the next activity lazily starts a fresh one.
+    fn suspend_for_idle(&mut self, cx: &mut ModelContext<Self>) {
+        if !matches!(self.server, CodyServer::Running(_)) {
+            return;
+        }
+        if let CodyServer::Running(server) = mem::replace(&mut self.server, CodyServer::Suspended)
+        {
+            server.lsp.shutdown();
+        }
+        cx.notify();
+    }
+
+    /// Whether completions are currently suppressed after a rate-limit error, and if so, how
+    /// much longer the current cooldown has left. Computed from `rate_limited_until` at call
+    /// time, so it stays accurate throughout the cooldown instead of reporting its starting
+    /// value until it suddenly clears.
+    pub fn rate_limited_for(&self) -> Option<Duration> {
+        self.rate_limited_until
+            .map(|until| until.saturating_duration_since(Instant::now()))
+    }
+
+    /// Suppresses completions for `self.rate_limit_backoff`, doubling it (up to
+    /// `RATE_LIMIT_COOLDOWN_MAX`) for the next consecutive rate limit, and automatically lifting
+    /// the suppression once the cooldown elapses.
+    fn enter_rate_limit_cooldown(&mut self, cx: &mut ModelContext<Self>) {
+        let cooldown = self.rate_limit_backoff;
+        self.rate_limited_until = Some(Instant::now() + cooldown);
+        self.rate_limit_backoff = (self.rate_limit_backoff * 2).min(RATE_LIMIT_COOLDOWN_MAX);
+        self.rate_limit_cooldown_task = cx.spawn(|this, mut cx| async move {
+            cx.background_executor().timer(cooldown).await;
+            this.update(&mut cx, |this, cx| {
+                this.rate_limited_until = None;
+                cx.notify();
+            })
+            .ok();
+        });
+        cx.emit(Event::RateLimited { cooldown });
+        cx.notify();
+    }
+
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn fake(cx: &mut gpui::TestAppContext) -> (Model<Self>, lsp::FakeLanguageServer) {
+        use lsp::FakeLanguageServer;
+        use node_runtime::FakeNodeRuntime;
+
+        let (server, fake_server) = FakeLanguageServer::new(
+            LanguageServerId(0),
+            LanguageServerBinary {
+                path: "path/to/cody".into(),
+                arguments: vec![],
+                env: None,
+            },
+            "cody".into(),
+            Default::default(),
+            cx.to_async(),
+        );
+        let http = util::http::FakeHttpClient::create(|_| async { unreachable!() });
+        let node_runtime = FakeNodeRuntime::new();
+        let this = cx.new_model(|cx| Self {
+            server_id: LanguageServerId(0),
+            http: http.clone(),
+            node_runtime,
+            server: CodyServer::Running(RunningCodyServer {
+                name: LanguageServerName(Arc::from("cody")),
+                lsp: Arc::new(server),
+                sign_in_status: SignInStatus::Authorized { verified: true },
+                username: None,
+                registered_buffers: Default::default(),
+                pending_opens: Task::ready(()).shared(),
+                activity: None,
+                supports_incremental_sync: true,
+                accept_completion_method_v2: false,
+                completion_cache: CompletionCache {
+                    capacity: 50,
+                    ..Default::default()
+                },
+                pending_telemetry: Vec::new(),
+                next_telemetry_id: 0,
+            }),
+            current_endpoint: CODY_AUTH_URL.to_string(),
+            known_accounts: vec![CODY_AUTH_URL.to_string()],
+            idle_shutdown_task: Task::ready(()),
+            rate_limited_until: None,
+            rate_limit_backoff: RATE_LIMIT_COOLDOWN_INITIAL,
+            rate_limit_cooldown_task: Task::ready(()),
+            _subscription: cx.on_app_quit(Self::shutdown_language_server),
+            buffers: Default::default(),
+            recently_edited_buffers: Default::default(),
+            log_entries: Default::default(),
+            download_progress: None,
+            last_error: None,
+            selected_repos: Vec::new(),
+        });
+        (this, fake_server)
+    }
+
+    /// Starts the agent process. `attempt`/`max_attempts` let callers retry a transient failure
+    /// without the user ever seeing an intermediate `CodyServer::Error` state: on failure, this
+    /// only lands in `Error` once `attempt` reaches `max_attempts`, leaving `self.server`
+    /// untouched (still `Starting`) otherwise so the caller can back off and try again.
+    fn start_language_server(
+        new_server_id: LanguageServerId,
+        http: Arc<dyn HttpClient>,
+        node_runtime: Arc<dyn NodeRuntime>,
+        endpoint: String,
+        attempt: u32,
+        max_attempts: u32,
+        this: WeakModel<Self>,
+        mut cx: AsyncAppContext,
+    ) -> impl Future<Output = ()> {
+        async move {
+            let start_language_server = async {
+                let agent_version =
+                    cx.update(|cx| CodySettings::get_global(cx).agent_version.clone())?;
+                let on_progress = {
+                    let this = this.clone();
+                    let cx = cx.clone();
+                    move |percent: Option<u8>| {
+                        let mut cx = cx.clone();
+                        this.update(&mut cx, |this, cx| {
+                            this.download_progress = Some(match percent {
+                                Some(percent) => DownloadProgress::Determinate(percent),
+                                None => DownloadProgress::Indeterminate,
+                            });
+                            cx.notify();
+                        })
+                        .ok();
+                    }
+                };
+                let server_path = get_cody_lsp(http, agent_version, &on_progress).await?;
+                this.update(&mut cx, |this, cx| {
+                    this.download_progress = None;
+                    cx.notify();
+                })?;
+                let configured_node_path =
+                    cx.update(|cx| CodySettings::get_global(cx).node_path.clone())?;
+                let node_path = match configured_node_path {
+                    Some(node_path) => {
+                        let node_path = PathBuf::from(node_path);
+                        validate_node_path(&node_path).await?;
+                        node_path
+                    }
+                    None => node_runtime
+                        .binary_path()
+                        .await
+                        .context("Cody requires Node; download failed")?,
+                };
+                let arguments: Vec<OsString> = vec![server_path.into(), "--stdio".into()];
+
+                let mut env = HashMap::default();
+                let trace_enabled = cx.update(|cx| CodySettings::get_global(cx).trace)?;
+                if trace_enabled {
+                    env.insert(
+                        "CODY_AGENT_TRACE_PATH".to_string(),
+                        paths::LOGS_DIR
+                            .join("cody-agent-trace.json")
+                            .to_string_lossy()
+                            .into_owned(),
+                    );
+                }
+
+                if let Some(proxy) = http_proxy_from_env() {
+                    let proxy = proxy.to_string();
+                    env.insert("HTTP_PROXY".to_string(), proxy.clone());
+                    env.insert("HTTPS_PROXY".to_string(), proxy);
+                }
+                if let Ok(no_proxy) = std::env::var("NO_PROXY") {
+                    env.insert("NO_PROXY".to_string(), no_proxy);
+                }
+
+                let extra_headers = cx.update(|cx| CodySettings::get_global(cx).extra_headers.clone())?;
+                if !extra_headers.is_empty() {
+                    for name in extra_headers.keys() {
+                        validate_header_name(name)?;
+                    }
+                    env.insert(
+                        "CODY_AGENT_EXTRA_HEADERS".to_string(),
+                        serde_json::to_string(&extra_headers)?,
+                    );
+                }
+
+                let binary = LanguageServerBinary {
+                    path: node_path,
+                    arguments,
+                    env: Some(env),
+                };
+
+                let server = LanguageServer::new(
+                    Arc::new(Mutex::new(None)),
+                    new_server_id,
+                    binary,
+                    Path::new("/"),
+                    None,
+                    cx.clone(),
+                )?;
+
+                server
+                    .on_notification::<StatusNotification, _>({
+                        let this = this.clone();
+                        move |params, mut cx| {
+                            this.update(&mut cx, |this, cx| {
+                                if let Ok(server) = this.server.as_running() {
+                                    server.activity = match params.status.as_str() {
+                                        "InProgress" => Some(Arc::from(params.message.clone())),
+                                        _ => None,
+                                    };
+                                    cx.notify();
+                                }
+                                this.push_log_entry(
+                                    CodyLogEntry {
+                                        level: log::Level::Info,
+                                        message: format!("{}: {}", params.status, params.message),
+                                    },
+                                    cx,
+                                );
+                            })
+                            .ok();
+                        }
+                    })
+                    .detach();
+
+                server
+                    .on_notification::<request::CompletionChunk, _>({
+                        let this = this.clone();
+                        move |params, mut cx| {
+                            this.update(&mut cx, |_, cx| {
+                                cx.emit(Event::CompletionChunkReceived {
+                                    uuid: params.uuid,
+                                    text: params.text,
+                                });
+                            })
+                            .ok();
+                        }
+                    })
+                    .detach();
+
+                let log_level = cx.update(|cx| CodySettings::get_global(cx).log_level.clone())?;
+                let log_level = log_level
+                    .parse::<log::LevelFilter>()
+                    .unwrap_or(log::LevelFilter::Info);
+                server
+                    .on_notification::<request::LogMessage, _>({
+                        let this = this.clone();
+                        move |params, mut cx| {
+                            let level = match params.level {
+                                1 => log::Level::Error,
+                                2 => log::Level::Warn,
+                                3 => log::Level::Info,
+                                4 => log::Level::Debug,
+                                _ => log::Level::Trace,
+                            };
+                            if log::LevelFilter::from(level) > log_level {
+                                return;
+                            }
+                            log::log!(level, "{}: {}", params.metadata_str, params.message);
+                            this.update(&mut cx, |this, cx| {
+                                this.push_log_entry(
+                                    CodyLogEntry {
+                                        level,
+                                        message: format!(
+                                            "{}: {}",
+                                            params.metadata_str, params.message
+                                        ),
+                                    },
+                                    cx,
+                                );
+                            })
+                            .ok();
+                        }
+                    })
+                    .detach();
+
+                let access_token = Self::access_token(&endpoint, &cx).await.log_err().flatten();
+                let extension_configuration = access_token.map(|access_token| {
+                    request::ExtensionConfiguration {
+                        access_token,
+                        server_endpoint: endpoint.clone(),
+                    }
+                });
+
+                let server = cx.update(|cx| server.initialize(None, cx))?.await?;
+
+                // Independent of any particular request type, so an agent that dies while the
+                // user is only using chat/edit/explain/generate_tests (never triggering a
+                // completion request) still gets detected and restarted instead of leaving Cody
+                // silently stuck on a dead process.
+                server
+                    .on_exit({
+                        let this = this.clone();
+                        let mut cx = cx.clone();
+                        move || {
+                            this.update(&mut cx, |this, cx| this.handle_agent_crash(cx)).ok();
+                        }
+                    })
+                    .detach();
+
+                let accept_completion_method_v2 = server
+                    .version()
+                    .and_then(|version| semver::Version::parse(version).ok())
+                    .is_some_and(|version| version >= ACCEPT_COMPLETION_METHOD_V2_MIN_VERSION);
+                let supports_incremental_sync = matches!(
+                    server.capabilities().text_document_sync,
+                    Some(lsp::TextDocumentSyncCapability::Kind(
+                        lsp::TextDocumentSyncKind::INCREMENTAL
+                    )) | Some(lsp::TextDocumentSyncCapability::Options(
+                        lsp::TextDocumentSyncOptions {
+                            change: Some(lsp::TextDocumentSyncKind::INCREMENTAL),
+                            ..
+                        }
+                    ))
+                );
+                // Absence of `execute_command_provider` means the agent doesn't advertise its
+                // supported commands at all, which every agent version we've tested against does
+                // -- treat that as "unknown, assume supported" rather than refusing to ever
+                // offer completions against it.
+                let supports_completions = match server.capabilities().execute_command_provider.as_ref() {
+                    Some(provider) => provider
+                        .commands
+                        .iter()
+                        .any(|command| command == request::GetCompletions::METHOD),
+                    None => true,
+                };
+
+                // Detect an already-signed-in user so they aren't forced through sign-in again
+                // on every launch; `update_sign_in_status` re-registers any open buffers once
+                // this comes back authorized.
+                let status = server
+                    .request::<request::CheckStatus>(request::CheckStatusParams {
+                        local_checks_only: false,
+                    })
+                    .await?;
+
+                let app_version = cx.update(|cx| release_channel::AppVersion::global(cx))?;
+                server
+                    .request::<request::SetEditorInfo>(request::SetEditorInfoParams {
+                        editor_info: request::EditorInfo {
+                            name: "Zed".into(),
+                            version: app_version.to_string(),
+                        },
+                        editor_plugin_info: request::EditorPluginInfo {
+                            name: "zed-cody".into(),
+                            version: env!("CARGO_PKG_VERSION").into(),
+                        },
+                    })
+                    .await?;
+
+                anyhow::Ok((
+                    server,
+                    status,
+                    extension_configuration,
+                    supports_incremental_sync,
+                    accept_completion_method_v2,
+                    supports_completions,
+                ))
+            };
+
+            let server = start_language_server.await;
+            this.update(&mut cx, |this, cx| {
+                cx.notify();
+                match server {
+                    Ok((
+                        server,
+                        status,
+                        extension_configuration,
+                        supports_incremental_sync,
+                        accept_completion_method_v2,
+                        supports_completions,
+                    )) => {
+                        if extension_configuration.is_none() {
+                            let error: Arc<str> =
+                                "No access token configured. Set SRC_ACCESS_TOKEN to use Cody."
+                                    .into();
+                            this.last_error = Some(error.clone());
+                            this.server = CodyServer::Error(error);
+                            return;
+                        }
+                        if !supports_completions {
+                            let error: Arc<str> =
+                                "This Cody agent version doesn't support completions.".into();
+                            this.last_error = Some(error.clone());
+                            this.server = CodyServer::Error(error);
+                            return;
+                        }
+                        this.server = CodyServer::Running(RunningCodyServer {
+                            name: LanguageServerName(Arc::from("cody")),
+                            lsp: server,
+                            sign_in_status: SignInStatus::SignedOut,
+                            username: None,
+                            registered_buffers: Default::default(),
+                            pending_opens: Task::ready(()).shared(),
+                            activity: None,
+                            supports_incremental_sync,
+                            accept_completion_method_v2,
+                            completion_cache: CompletionCache {
+                                capacity: CodySettings::get_global(cx).completion_cache_size,
+                                ..Default::default()
+                            },
+                            pending_telemetry: Vec::new(),
+                            next_telemetry_id: 0,
+                        });
+                        this.last_error = None;
+                        cx.emit(Event::CodyLanguageServerStarted);
+                        this.update_sign_in_status(status, cx);
+                    }
+                    Err(error) => {
+                        if attempt < max_attempts {
+                            return;
+                        }
+                        let suffix = if max_attempts > 1 {
+                            format!(" (gave up after {attempt} attempts)")
+                        } else {
+                            String::new()
+                        };
+                        let error: Arc<str> = format!(
+                            "{error}. If you're behind a proxy, make sure HTTP_PROXY/HTTPS_PROXY are set.{suffix}"
+                        )
+                        .into();
+                        this.last_error = Some(error.clone());
+                        this.server = CodyServer::Error(error);
+                        cx.notify()
+                    }
+                }
+            })
+            .ok();
+        }
+    }
+
+    pub fn sign_in(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        if let CodyServer::Running(server) = &mut self.server {
+            let task = match &server.sign_in_status {
+                SignInStatus::Authorized { .. } => Task::ready(Ok(())).shared(),
+                SignInStatus::SigningIn { task, .. } => {
+                    cx.notify();
+                    task.clone()
+                }
+                SignInStatus::SignedOut | SignInStatus::Unauthorized { .. } => {
+                    let lsp = server.lsp.clone();
+                    let task = cx
+                        .spawn(|this, mut cx| async move {
+                            let sign_in = async {
+                                let sign_in = lsp
+                                    .request::<request::SignInInitiate>(
+                                        request::SignInInitiateParams {},
+                                    )
+                                    .await?;
+                                match sign_in {
+                                    request::SignInInitiateResult::AlreadySignedIn { user } => {
+                                        Ok(request::SignInStatus::Ok { user: Some(user) })
+                                    }
+                                    request::SignInInitiateResult::PromptUserDeviceFlow(flow) => {
+                                        this.update(&mut cx, |this, cx| {
+                                            if let CodyServer::Running(RunningCodyServer {
+                                                sign_in_status: status,
+                                                ..
+                                            }) = &mut this.server
+                                            {
+                                                if let SignInStatus::SigningIn {
+                                                    prompt: prompt_flow,
+                                                    ..
+                                                } = status
+                                                {
+                                                    *prompt_flow = Some(flow.clone());
+                                                    cx.notify();
+                                                }
+                                            }
+                                        })?;
+                                        let mut backoff = SIGN_IN_CONFIRM_INITIAL_BACKOFF;
+                                        let mut elapsed = Duration::ZERO;
+                                        loop {
+                                            match lsp
+                                                .request::<request::SignInConfirm>(
+                                                    request::SignInConfirmParams {
+                                                        user_code: flow.user_code.clone(),
+                                                    },
+                                                )
+                                                .await
+                                            {
+                                                Ok(response) => break Ok(response),
+                                                Err(error) if elapsed < SIGN_IN_CONFIRM_TIMEOUT => {
+                                                    this.update(&mut cx, |_, cx| cx.notify())?;
+                                                    cx.background_executor().timer(backoff).await;
+                                                    elapsed += backoff;
+                                                    backoff = (backoff * 2)
+                                                        .min(SIGN_IN_CONFIRM_MAX_BACKOFF);
+                                                }
+                                                Err(error) => {
+                                                    break Err(anyhow!(
+                                                        "timed out waiting for the device flow to complete: {}",
+                                                        error
+                                                    ));
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            };
+
+                            let sign_in = sign_in.await;
+                            this.update(&mut cx, |this, cx| match sign_in {
+                                Ok(status) => {
+                                    this.update_sign_in_status(status, cx);
+                                    Ok(())
+                                }
+                                Err(error) => {
+                                    this.update_sign_in_status(
+                                        request::SignInStatus::NotSignedIn,
+                                        cx,
+                                    );
+                                    Err(Arc::new(error))
+                                }
+                            })?
+                        })
+                        .shared();
+                    server.sign_in_status = SignInStatus::SigningIn {
+                        prompt: None,
+                        task: task.clone(),
+                    };
+                    cx.notify();
+                    task
+                }
+            };
+
+            cx.background_executor()
+                .spawn(task.map_err(|err| anyhow!("{:?}", err)))
+        } else {
+            Task::ready(Err(anyhow!("cody hasn't started yet")))
+        }
+    }
+
+    /// Signs in to the current endpoint with a personal access token instead of the
+    /// `SignInInitiate` device flow, for Enterprise instances that don't support it: stores
+    /// `token`, pushes it to the agent, and re-checks sign-in status against it, the same way
+    /// `switch_account` does after changing endpoints.
+    pub fn sign_in_with_token(&mut self, token: String, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let endpoint = self.current_endpoint.clone();
+        cx.spawn(|this, mut cx| async move {
+            cx.update(|cx| Self::set_access_token(&endpoint, token, cx))?
+                .await?;
+            this.update(&mut cx, |this, cx| this.notify_configuration_changed(cx))?
+                .await
+        })
+    }
+
+    /// Abandons an in-progress device flow, dropping the shared `SignInConfirm` polling task and
+    /// resetting to `SignedOut` so a subsequent `sign_in` starts a fresh device flow rather than
+    /// returning the task we just abandoned.
+    pub fn cancel_sign_in(&mut self, cx: &mut ModelContext<Self>) {
+        if let Ok(server) = self.server.as_running() {
+            if matches!(server.sign_in_status, SignInStatus::SigningIn { .. }) {
+                server.sign_in_status = SignInStatus::SignedOut;
+                cx.notify();
+            }
+        }
+    }
+
+    /// Signs out of the agent. State only flips to `SignedOut` once the `SignOut` request
+    /// actually succeeds -- if it fails, Cody stays in whatever sign-in state it was already in,
+    /// rather than optimistically reporting signed-out while the agent still considers itself
+    /// authenticated.
+    pub fn sign_out(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let Ok(server) = self.server.as_running() else {
+            return Task::ready(Err(anyhow!("cody hasn't started yet")));
+        };
+        let server = server.lsp.clone();
+        cx.spawn(|this, mut cx| async move {
+            server
+                .request::<request::SignOut>(request::SignOutParams {})
+                .await?;
+            this.update(&mut cx, |this, cx| {
+                this.update_sign_in_status(request::SignInStatus::NotSignedIn, cx)
+            })
+        })
+    }
+
+    pub fn edit(
+        &mut self,
+        buffer: &Model<Buffer>,
+        range: Range<Anchor>,
+        instruction: String,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<String>> {
+        self.register_buffer(buffer, cx);
+
+        let server = match self.server.as_authenticated() {
+            Ok(server) => server,
+            Err(error) => return Task::ready(Err(error)),
+        };
+        let lsp = server.lsp.clone();
+        let registered_buffer = server
+            .registered_buffers
+            .get_mut(&buffer.entity_id())
+            .unwrap();
+        let snapshot = registered_buffer.report_changes(buffer, cx);
+        let uri = registered_buffer.uri.clone();
+        let snapshot_now = buffer.read(cx).snapshot();
+        let range = range.to_point_utf16(&snapshot_now);
+
+        cx.background_executor().spawn(async move {
+            let (_, snapshot) = snapshot.await?;
+            let range = lsp::Range::new(
+                point_to_lsp(snapshot.clip_point_utf16(range.start, Bias::Left)),
+                point_to_lsp(snapshot.clip_point_utf16(range.end, Bias::Left)),
+            );
+            let result = lsp
+                .request::<request::EditCommand>(request::EditCommandParams {
+                    uri,
+                    range,
+                    instruction,
+                })
+                .await?;
+            Ok(result.new_text)
+        })
+    }
+
+    pub fn chat(&mut self, id: String, text: String, cx: &mut ModelContext<Self>) -> Task<Result<Vec<request::ChatMessage>>> {
+        self.reset_idle_timer(cx);
+        let server = match self.server.as_authenticated() {
+            Ok(server) => server,
+            Err(error) => return Task::ready(Err(error)),
+        };
+        let repos = self.selected_repos.iter().map(|repo| repo.id.clone()).collect();
+        let request =
+            server
+                .lsp
+                .request::<request::ChatSubmitMessage>(request::ChatSubmitMessageParams {
+                    id,
+                    message: request::ChatMessage {
+                        speaker: request::ChatSpeaker::Human,
+                        text,
+                    },
+                    repos,
+                });
+        cx.background_executor().spawn(async move {
+            let result = request.await?;
+            Ok(result.messages)
+        })
+    }
+
+    /// Searches the Enterprise instance's remote repositories by fuzzy name match, for a custom
+    /// UI (e.g. the chat panel's repo picker) to populate a `set_selected_repos` call from.
+    pub fn search_repos(
+        &mut self,
+        query: String,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<request::Repo>>> {
+        let server = match self.server.as_authenticated() {
+            Ok(server) => server,
+            Err(error) => return Task::ready(Err(error)),
+        };
+        let request = server.lsp.request::<request::Repos>(request::ReposParams {
+            query,
+            first: 20,
+        });
+        cx.background_executor().spawn(async move {
+            let result = request.await?;
+            Ok(result.repos)
+        })
+    }
+
+    /// The remote repositories `chat` currently scopes its requests to. Empty means the agent
+    /// falls back to its own default scope.
+    pub fn selected_repos(&self) -> &[request::Repo] {
+        &self.selected_repos
+    }
+
+    /// Sets which remote repositories `chat` should scope its requests to. A custom UI is
+    /// responsible for persisting this selection (e.g. per workspace) across restarts -- `Cody`
+    /// itself only holds it for the current session.
+    pub fn set_selected_repos(&mut self, repos: Vec<request::Repo>, cx: &mut ModelContext<Self>) {
+        self.selected_repos = repos;
+        cx.notify();
+    }
+
+    /// Asks the agent to explain `range` of `buffer`, as a chat message on its own dedicated
+    /// conversation (distinct from the main chat panel's), so repeated explanations don't pile
+    /// up in a single back-and-forth history.
+    pub fn explain(
+        &mut self,
+        buffer: &Model<Buffer>,
+        range: Range<Anchor>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<request::ChatMessage>>> {
+        let buffer = buffer.read(cx);
+        let language_id = id_for_language(buffer.language());
+        let selected_text = buffer.snapshot().text_for_range(range).collect::<String>();
+        let prompt =
+            format!("Explain the following {language_id} code:\n\n```{language_id}\n{selected_text}\n```");
+        self.chat(EXPLAIN_SELECTION_CHAT_ID.to_string(), prompt, cx)
+    }
+
+    /// Asks the agent to write a unit test for `range` of `buffer`, returning just the generated
+    /// test code (with any surrounding chat prose and code fences stripped) so callers can insert
+    /// it directly into a buffer.
+    pub fn generate_tests(
+        &mut self,
+        buffer: &Model<Buffer>,
+        range: Range<Anchor>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<String>> {
+        let buffer = buffer.read(cx);
+        let language_id = id_for_language(buffer.language());
+        let selected_text = buffer.snapshot().text_for_range(range).collect::<String>();
+        let prompt = format!(
+            "Write a unit test for the following {language_id} code. Reply with only the test \
+             code, in a single fenced code block, and no other prose:\n\n```{language_id}\n{selected_text}\n```"
+        );
+        let chat = self.chat(GENERATE_TESTS_CHAT_ID.to_string(), prompt, cx);
+        cx.background_executor().spawn(async move {
+            let messages = chat.await?;
+            let response = messages
+                .into_iter()
+                .rev()
+                .find(|message| message.speaker == request::ChatSpeaker::Assistant)
+                .map(|message| message.text)
+                .unwrap_or_default();
+            Ok(extract_code_block(&response))
+        })
+    }
+
+    pub fn reinstall(&mut self, cx: &mut ModelContext<Self>) -> Task<()> {
+        let start_task = cx
+            .spawn({
+                let http = self.http.clone();
+                let node_runtime = self.node_runtime.clone();
+                let server_id = self.server_id;
+                let endpoint = self.current_endpoint.clone();
+                move |this, cx| async move {
+                    clear_cody_dir().await;
+                    Self::start_language_server(server_id, http, node_runtime, endpoint, 1, 1, this, cx)
+                        .await
+                }
+            })
+            .shared();
+
+        self.server = CodyServer::Starting {
+            task: start_task.clone(),
+        };
+
+        cx.notify();
+
+        cx.background_executor().spawn(start_task)
+    }
+
+    /// Called when the agent process exits unexpectedly -- detected either by
+    /// `LanguageServer::on_exit` firing on its own (independent of any request) or by a request
+    /// failing with `is_server_shut_down_error` -- as opposed to an intentional
+    /// `restart`/`reinstall`/`shutdown_language_server`. Restarts it using the already-downloaded
+    /// binary and emits `Event::AgentCrashed`, instead of leaving `Cody` silently stuck on a dead
+    /// `Arc<LanguageServer>` until the user notices and manually reinstalls. A no-op if
+    /// `self.server` has already moved on (e.g. a restart is already in flight, or the user signed
+    /// out in the meantime).
+    fn handle_agent_crash(&mut self, cx: &mut ModelContext<Self>) {
+        if !matches!(self.server, CodyServer::Running(_)) {
+            return;
+        }
+        cx.emit(Event::AgentCrashed);
+        self.restart(cx).detach();
+    }
+
+    /// Restarts the agent process without re-downloading or clearing it, unlike `reinstall`.
+    /// `start_language_server` checks sign-in status on every launch, so an already-signed-in
+    /// user lands back in `Authorized` with their buffers re-registered once the restart
+    /// completes.
+    pub fn restart(&mut self, cx: &mut ModelContext<Self>) -> Task<()> {
+        let start_task = cx
+            .spawn({
+                let http = self.http.clone();
+                let node_runtime = self.node_runtime.clone();
+                let server_id = self.server_id;
+                let endpoint = self.current_endpoint.clone();
+                move |this, cx| {
+                    Self::start_language_server(server_id, http, node_runtime, endpoint, 1, 1, this, cx)
+                }
+            })
+            .shared();
+
+        self.server = CodyServer::Starting {
+            task: start_task.clone(),
+        };
+
+        cx.notify();
+
+        cx.background_executor().spawn(start_task)
+    }
+
+    /// Persists the access token to the platform keychain, keyed by `endpoint` so tokens for
+    /// different Sourcegraph instances don't collide. Falls back to the `SRC_ACCESS_TOKEN`
+    /// environment variable for the default endpoint only if no token has ever been stored.
+    pub fn set_access_token(endpoint: &str, access_token: String, cx: &AppContext) -> Task<Result<()>> {
+        cx.write_credentials(endpoint, "Bearer", access_token.as_bytes())
+    }
+
+    async fn access_token(endpoint: &str, cx: &AsyncAppContext) -> Result<Option<String>> {
+        let credentials = cx.update(|cx| cx.read_credentials(endpoint))?.await?;
+        if let Some((_, access_token)) = credentials {
+            return Ok(Some(String::from_utf8(access_token)?));
+        }
+        if endpoint == CODY_AUTH_URL {
+            return Ok(std::env::var("SRC_ACCESS_TOKEN").ok());
+        }
+        Ok(None)
+    }
+
+    /// Switches completions and chat to a different Sourcegraph instance at runtime: stores
+    /// `token` for `url`, remembers `url` for the account-switcher menu, and pushes the new
+    /// endpoint and token to the already-running agent so it reconnects without a full restart.
+    pub fn set_endpoint(&mut self, url: String, token: String, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        if !self.known_accounts.contains(&url) {
+            self.known_accounts.push(url.clone());
+        }
+        self.current_endpoint = url.clone();
+        self.persist_state(cx).detach_and_log_err(cx);
+        cx.spawn(|this, mut cx| async move {
+            cx.update(|cx| Self::set_access_token(&url, token, cx))?.await?;
+            this.update(&mut cx, |this, cx| this.notify_configuration_changed(cx))?
+                .await
+        })
+    }
+
+    /// Switches to an endpoint already in `known_accounts`, reusing its stored access token.
+    pub fn switch_account(&mut self, endpoint: String, cx: &mut ModelContext<Self>) -> Task<()> {
+        if endpoint == self.current_endpoint {
+            return Task::ready(());
+        }
+        self.current_endpoint = endpoint;
+        self.persist_state(cx).detach_and_log_err(cx);
+        let notify = self.notify_configuration_changed(cx);
+        cx.background_executor().spawn(async move {
+            notify.await.log_err();
+        })
+    }
+
+    /// Pushes `current_endpoint`'s access token to the already-running agent via
+    /// `workspace/didChangeConfiguration`, then re-checks sign-in status against it, so an
+    /// endpoint or credential change is picked up without respawning the agent process.
+    fn notify_configuration_changed(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let Ok(server) = self.server.as_running() else {
+            return Task::ready(Ok(()));
+        };
+        let lsp = server.lsp.clone();
+        let endpoint = self.current_endpoint.clone();
+        cx.spawn(|this, mut cx| async move {
+            let access_token = Self::access_token(&endpoint, &cx).await?.unwrap_or_default();
+            let settings = serde_json::to_value(request::ExtensionConfiguration {
+                access_token,
+                server_endpoint: endpoint,
+            })?;
+            lsp.notify::<lsp::notification::DidChangeConfiguration>(
+                lsp::DidChangeConfigurationParams { settings },
+            )
+            .ok();
+            let status = lsp
+                .request::<request::CheckStatus>(request::CheckStatusParams {
+                    local_checks_only: false,
+                })
+                .await?;
+            this.update(&mut cx, |this, cx| this.update_sign_in_status(status, cx))
+        })
+    }
+
+    pub fn current_endpoint(&self) -> &str {
+        &self.current_endpoint
+    }
+
+    pub fn known_accounts(&self) -> &[String] {
+        &self.known_accounts
+    }
+
+    pub fn language_server(&self) -> Option<(&LanguageServerName, &Arc<LanguageServer>)> {
+        if let CodyServer::Running(server) = &self.server {
+            Some((&server.name, &server.lsp))
+        } else {
+            None
+        }
+    }
+
+    pub fn register_buffer(&mut self, buffer: &Model<Buffer>, cx: &mut ModelContext<Self>) {
+        if !cody_enabled_for_buffer(buffer, cx) {
+            return;
+        }
+
+        if buffer
+            .read(cx)
+            .file()
+            .map_or(false, |file| file.is_private())
+        {
+            return;
+        }
+
+        let max_file_size = CodySettings::get_global(cx).max_file_size;
+        if buffer.read(cx).len() as u64 > max_file_size {
+            return;
+        }
+
+        if matches!(self.server, CodyServer::Suspended) {
+            self.enable_or_disable_cody(cx);
+        }
+        self.reset_idle_timer(cx);
+
+        let weak_buffer = buffer.downgrade();
+        self.buffers.insert(weak_buffer.clone());
+
+        if let CodyServer::Running(RunningCodyServer {
+            lsp: server,
+            sign_in_status: status,
+            registered_buffers,
+            pending_opens,
+            ..
+        }) = &mut self.server
+        {
+            if !matches!(status, SignInStatus::Authorized { .. }) {
+                return;
+            }
+
+            registered_buffers
+                .entry(buffer.entity_id())
+                .or_insert_with(|| {
+                    let uri: lsp::Url = uri_for_buffer(buffer, cx);
+                    let language_id = id_for_language(buffer.read(cx).language());
+                    let snapshot = buffer.read(cx).snapshot();
+
+                    // Queue the `DidOpenTextDocument` through the background executor, one at a
+                    // time, instead of sending it inline here, so registering many buffers at
+                    // once doesn't build+send them all synchronously back to back.
+                    let prev_open = pending_opens.clone();
+                    let lsp = server.clone();
+                    let open_params = lsp::DidOpenTextDocumentParams {
+                        text_document: lsp::TextDocumentItem {
+                            uri: uri.clone(),
+                            language_id: language_id.clone(),
+                            version: 0,
+                            text: snapshot.text(),
+                        },
+                    };
+                    let open_task = cx
+                        .background_executor()
+                        .spawn(async move {
+                            prev_open.await;
+                            lsp.notify::<lsp::notification::DidOpenTextDocument>(open_params)
+                                .log_err();
+                        })
+                        .shared();
+                    *pending_opens = open_task.clone();
+
+                    RegisteredBuffer {
+                        uri,
+                        language_id,
+                        snapshot,
+                        snapshot_version: 0,
+                        // Any change report for this buffer must wait for its queued open to
+                        // actually flush first, so the agent never sees a `didChange`/completion
+                        // request for a document it hasn't been told is open yet.
+                        pending_buffer_change: cx.background_executor().spawn(async move {
+                            open_task.await;
+                            Some(())
+                        }),
+                        pending_completions: 0,
+                        last_completions: Vec::new(),
+                        muted: false,
+                        _subscriptions: [
+                            cx.subscribe(buffer, |this, buffer, event, cx| {
+                                this.handle_buffer_event(buffer, event, cx).log_err();
+                            }),
+                            cx.observe_release(buffer, move |this, _buffer, _cx| {
+                                this.buffers.remove(&weak_buffer);
+                                this.unregister_buffer(&weak_buffer);
+                            }),
+                        ],
+                    }
+                });
+        }
+    }
+
+    /// Moves `buffer` to the front of `recently_edited_buffers`, capped at
+    /// `RECENTLY_EDITED_BUFFERS_LIMIT`, so the most recently edited buffers are the ones offered
+    /// as `cody.context.include_open_files` candidates.
+    fn touch_recently_edited_buffer(&mut self, buffer: WeakModel<Buffer>) {
+        self.recently_edited_buffers.retain(|existing| existing != &buffer);
+        self.recently_edited_buffers.push_front(buffer);
+        self.recently_edited_buffers
+            .truncate(RECENTLY_EDITED_BUFFERS_LIMIT);
+    }
+
+    /// Builds the `cody.context.include_open_files` payload for a completion request on
+    /// `buffer`: up to `cody.context.max_bytes` of other recently-edited, eligible buffers'
+    /// content.
+    fn context_files_for_completion(
+        &self,
+        buffer: &Model<Buffer>,
+        cx: &AppContext,
+    ) -> Vec<request::GetCompletionsContextFile> {
+        let context_settings = &CodySettings::get_global(cx).context;
+        if !context_settings.include_open_files {
+            return Vec::new();
+        }
+
+        let mut remaining_bytes = context_settings.max_bytes;
+        let mut context_files = Vec::new();
+        for other_buffer in &self.recently_edited_buffers {
+            let Some(other_buffer) = other_buffer.upgrade() else {
+                continue;
+            };
+            if other_buffer.entity_id() == buffer.entity_id() {
+                continue;
+            }
+            if !cody_enabled_for_buffer(&other_buffer, cx)
+                || other_buffer
+                    .read(cx)
+                    .file()
+                    .map_or(false, |file| file.is_private())
+            {
+                continue;
+            }
+
+            let content = other_buffer.read(cx).text();
+            if content.len() > remaining_bytes {
+                continue;
+            }
+            remaining_bytes -= content.len();
+            context_files.push(request::GetCompletionsContextFile {
+                uri: uri_for_buffer(&other_buffer, cx),
+                content,
+            });
+        }
+        context_files
+    }
+
+    fn handle_buffer_event(
+        &mut self,
+        buffer: Model<Buffer>,
+        event: &language::Event,
+        cx: &mut ModelContext<Self>,
+    ) -> Result<()> {
+        if matches!(event, language::Event::Edited) {
+            self.touch_recently_edited_buffer(buffer.downgrade());
+        }
+
+        if let Ok(server) = self.server.as_running() {
+            if matches!(
+                event,
+                language::Event::FileHandleChanged | language::Event::LanguageChanged
+            ) && server.registered_buffers.contains_key(&buffer.entity_id())
+                && !cody_enabled_for_buffer(&buffer, cx)
+            {
+                if let Some(registered_buffer) =
+                    server.registered_buffers.remove(&buffer.entity_id())
+                {
+                    server
+                        .lsp
+                        .notify::<lsp::notification::DidCloseTextDocument>(
+                            lsp::DidCloseTextDocumentParams {
+                                text_document: lsp::TextDocumentIdentifier::new(
+                                    registered_buffer.uri,
+                                ),
+                            },
+                        )?;
+                }
+                return Ok(());
+            }
+
+            if let Some(registered_buffer) = server.registered_buffers.get_mut(&buffer.entity_id())
+            {
+                match event {
+                    language::Event::Edited => {
+                        let _ = registered_buffer.report_changes(&buffer, cx);
+                    }
+                    language::Event::Saved => {
+                        let text = CodySettings::get_global(cx)
+                            .include_text_on_save
+                            .then(|| buffer.read(cx).text());
+                        server
+                            .lsp
+                            .notify::<lsp::notification::DidSaveTextDocument>(
+                                lsp::DidSaveTextDocumentParams {
+                                    text_document: lsp::TextDocumentIdentifier::new(
+                                        registered_buffer.uri.clone(),
+                                    ),
+                                    text,
+                                },
+                            )?;
+                    }
+                    language::Event::FileHandleChanged | language::Event::LanguageChanged => {
+                        let new_language_id = id_for_language(buffer.read(cx).language());
+                        let new_uri = uri_for_buffer(&buffer, cx);
+                        if new_uri != registered_buffer.uri
+                            || new_language_id != registered_buffer.language_id
+                        {
+                            let old_uri = mem::replace(&mut registered_buffer.uri, new_uri);
+                            registered_buffer.language_id = new_language_id;
+                            // A `DidOpenTextDocument` always starts a document's version counter
+                            // over at 0 (as `register_buffer` does for a brand new document), so
+                            // this re-open must reset `snapshot_version` to match, rather than
+                            // carrying over whatever version the closed document had reached.
+                            registered_buffer.snapshot_version = 0;
+                            server
+                                .lsp
+                                .notify::<lsp::notification::DidCloseTextDocument>(
+                                    lsp::DidCloseTextDocumentParams {
+                                        text_document: lsp::TextDocumentIdentifier::new(old_uri),
+                                    },
+                                )?;
+                            server
+                                .lsp
+                                .notify::<lsp::notification::DidOpenTextDocument>(
+                                    lsp::DidOpenTextDocumentParams {
+                                        text_document: lsp::TextDocumentItem::new(
+                                            registered_buffer.uri.clone(),
+                                            registered_buffer.language_id.clone(),
+                                            registered_buffer.snapshot_version,
+                                            registered_buffer.snapshot.text(),
+                                        ),
+                                    },
+                                )?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn unregister_buffer(&mut self, buffer: &WeakModel<Buffer>) {
+        if let Ok(server) = self.server.as_running() {
+            if let Some(buffer) = server.registered_buffers.remove(&buffer.entity_id()) {
+                server
+                    .lsp
+                    .notify::<lsp::notification::DidCloseTextDocument>(
+                        lsp::DidCloseTextDocumentParams {
+                            text_document: lsp::TextDocumentIdentifier::new(buffer.uri),
+                        },
+                    )
+                    .log_err();
+            }
+        }
+    }
+
+    /// Closes every currently registered buffer's document in one pass, rather than looking each
+    /// one up individually through `unregister_buffer`. Used on sign-out, where iterating
+    /// `self.buffers` (which may include buffers that were never registered) and removing them
+    /// from `registered_buffers` one at a time is a needless `DidCloseTextDocument` notify storm
+    /// with hundreds of open buffers.
+    fn unregister_all_buffers(&mut self) {
+        let Ok(server) = self.server.as_running() else {
+            return;
+        };
+        for (_, buffer) in mem::take(&mut server.registered_buffers) {
+            server
+                .lsp
+                .notify::<lsp::notification::DidCloseTextDocument>(
+                    lsp::DidCloseTextDocumentParams {
+                        text_document: lsp::TextDocumentIdentifier::new(buffer.uri),
+                    },
+                )
+                .log_err();
+        }
+    }
+
+    pub fn completions<T>(
+        &mut self,
+        buffer: &Model<Buffer>,
+        position: T,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<Completion>>>
+    where
+        T: ToPointUtf16,
+    {
+        self.request_completions(buffer, position, false, cx)
+    }
+
+    /// Like `completions`, but for a buffer that shouldn't stay registered with the agent once
+    /// the request resolves (e.g. a short-lived preview buffer): if `buffer` isn't already
+    /// registered, it's unregistered again (closing its document) afterwards, instead of
+    /// accumulating forever in `registered_buffers`. If `buffer` is already registered
+    /// independently (e.g. because it's open in an editor), that registration is left alone.
+    pub fn completions_oneshot<T>(
+        &mut self,
+        buffer: &Model<Buffer>,
+        position: T,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<Completion>>>
+    where
+        T: ToPointUtf16,
+    {
+        let already_registered = matches!(
+            &self.server,
+            CodyServer::Running(server) if server.registered_buffers.contains_key(&buffer.entity_id())
+        );
+
+        let completions = self.completions(buffer, position, cx);
+        if already_registered {
+            return completions;
+        }
+
+        let weak_buffer = buffer.downgrade();
+        cx.spawn(|this, mut cx| async move {
+            let result = completions.await;
+            this.update(&mut cx, |this, _| {
+                this.buffers.remove(&weak_buffer);
+                this.unregister_buffer(&weak_buffer);
+            })
+            .ok();
+            result
+        })
+    }
+
+    pub fn completions_cycling<T>(
+        &mut self,
+        buffer: &Model<Buffer>,
+        position: T,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<Completion>>>
+    where
+        T: ToPointUtf16,
+    {
+        self.request_completions(buffer, position, true, cx)
+    }
+
+    /// Checks `completion.range` against the current snapshot of whichever registered buffer it
+    /// was anchored to, so a completion whose buffer was edited out from under it between request
+    /// and accept can be rejected instead of being reported to the agent as accepted for text that
+    /// no longer matches what it was generated from.
+    fn completion_range_is_current(&self, completion: &Completion, cx: &AppContext) -> bool {
+        let Some(buffer_id) = completion.range.start.buffer_id else {
+            return true;
+        };
+        let Some(buffer) = self
+            .buffers
+            .iter()
+            .filter_map(|buffer| buffer.upgrade())
+            .find(|buffer| buffer.read(cx).remote_id() == buffer_id)
+        else {
+            return false;
+        };
+        let snapshot = buffer.read(cx).snapshot();
+        completion.range.start.is_valid(&snapshot) && completion.range.end.is_valid(&snapshot)
+    }
+
+    pub fn accept_completion(
+        &mut self,
+        completion: &Completion,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        if !self.completion_range_is_current(completion, cx) {
+            return Task::ready(Err(anyhow!(
+                "completion is stale: its buffer changed since the completion was requested"
+            )));
+        }
+        let server = match self.server.as_authenticated() {
+            Ok(server) => server,
+            Err(error) => return Task::ready(Err(error)),
+        };
+        let params = request::NotifyAcceptedParams {
+            uuid: completion.uuid.clone(),
+        };
+        let request: BoxFuture<Result<String>> = if server.accept_completion_method_v2 {
+            server
+                .lsp
+                .request::<request::NotifyAcceptedV2>(params)
+                .boxed()
+        } else {
+            server
+                .lsp
+                .request::<request::NotifyAccepted>(params)
+                .boxed()
+        };
+        let latency = completion.requested_at.elapsed();
+        log::info!(
+            "cody: accepted completion {} {:?} after it was requested",
+            completion.uuid,
+            latency
+        );
+        cx.emit(Event::CompletionAccepted {
+            uuid: completion.uuid.clone(),
+            latency,
+        });
+        self.track_telemetry_request(request, cx);
+        Task::ready(Ok(()))
+    }
+
+    pub fn accept_partial_completion(
+        &mut self,
+        completion: &Completion,
+        accepted_len: usize,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        if !self.completion_range_is_current(completion, cx) {
+            return Task::ready(Err(anyhow!(
+                "completion is stale: its buffer changed since the completion was requested"
+            )));
+        }
+        let server = match self.server.as_authenticated() {
+            Ok(server) => server,
+            Err(error) => return Task::ready(Err(error)),
+        };
+        let request = server.lsp.request::<request::NotifyPartialAccept>(
+            request::NotifyPartialAcceptParams {
+                uuid: completion.uuid.clone(),
+                accepted_length: accepted_len,
+            },
+        );
+        self.track_telemetry_request(request, cx);
+        Task::ready(Ok(()))
+    }
+
+    pub fn discard_completions(
+        &mut self,
+        completions: &[Completion],
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let server = match self.server.as_authenticated() {
+            Ok(server) => server,
+            Err(_) => return Task::ready(Ok(())),
+        };
+        let request =
+            server
+                .lsp
+                .request::<request::NotifyRejected>(request::NotifyRejectedParams {
+                    uuids: completions
+                        .iter()
+                        .map(|completion| completion.uuid.clone())
+                        .collect(),
+                });
+        self.track_telemetry_request(request, cx);
+        Task::ready(Ok(()))
+    }
+
+    /// Spawns `request` (a `NotifyAccepted`/`NotifyAcceptedV2`/`NotifyPartialAccept`/
+    /// `NotifyRejected` request) and tracks it in `pending_telemetry` so `update_sign_in_status`
+    /// can cancel it on sign-out, removing it from `pending_telemetry` again once it resolves on
+    /// its own so a long session with many completions doesn't grow that list unboundedly.
+    fn track_telemetry_request<R: 'static>(
+        &mut self,
+        request: impl Future<Output = Result<R>> + Send + 'static,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let Ok(server) = self.server.as_authenticated() else {
+            return;
+        };
+        let id = server.next_telemetry_id;
+        server.next_telemetry_id += 1;
+        let task = cx.spawn(move |this, mut cx| async move {
+            request.await.log_err();
+            this.update(&mut cx, |this, _| {
+                if let Ok(server) = this.server.as_authenticated() {
+                    server.pending_telemetry.retain(|(task_id, _)| *task_id != id);
+                }
+            })
+            .ok();
+        });
+        server.pending_telemetry.push((id, task));
+    }
+
+    /// Drops any cached completions for `buffer`, so the next `completions` request for it hits
+    /// the agent again even if the buffer's content and version haven't changed. Used by
+    /// `Suggest` to force a fresh suggestion on demand rather than replaying a cached one.
+    pub fn invalidate_completion_cache(&mut self, buffer: &Model<Buffer>) {
+        let Ok(server) = self.server.as_authenticated() else {
+            return;
+        };
+        let Some(registered_buffer) = server.registered_buffers.get(&buffer.entity_id()) else {
+            return;
+        };
+        let uri = registered_buffer.uri.clone();
+        server.completion_cache.invalidate_uri(&uri);
+    }
+
+    fn request_completions<T>(
+        &mut self,
+        buffer: &Model<Buffer>,
+        position: T,
+        cycling: bool,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<Completion>>>
+    where
+        T: ToPointUtf16,
+    {
+        if let Some(cooldown) = self.rate_limited_for() {
+            return Task::ready(Err(anyhow!(
+                "cody is rate limited, retrying in {}s",
+                cooldown.as_secs()
+            )));
+        }
+
+        let disable_in_comments = CodySettings::get_global(cx).disable_in_comments;
+        let disable_in_strings = CodySettings::get_global(cx).disable_in_strings;
+        if disable_in_comments || disable_in_strings {
+            let point = position.to_point_utf16(buffer.read(cx));
+            if let Some(scope) = buffer.read(cx).language_scope_at(point) {
+                match scope.override_name() {
+                    Some("comment") if disable_in_comments => return Task::ready(Ok(Vec::new())),
+                    Some("string") if disable_in_strings => return Task::ready(Ok(Vec::new())),
+                    _ => {}
+                }
+            }
+        }
+
+        self.register_buffer(buffer, cx);
+        self.reset_idle_timer(cx);
+        cx.emit(Event::CompletionsRequested);
+
+        let server = match self.server.as_authenticated() {
+            Ok(server) => server,
+            Err(error) => return Task::ready(Err(error)),
+        };
+        let lsp = server.lsp.clone();
+        let registered_buffer = server
+            .registered_buffers
+            .get_mut(&buffer.entity_id())
+            .unwrap();
+        if registered_buffer.muted {
+            return Task::ready(Ok(Vec::new()));
+        }
+        registered_buffer.pending_completions += 1;
+        let snapshot = registered_buffer.report_changes(buffer, cx);
+        let uri = registered_buffer.uri.clone();
+        let context_mode = CodySettings::get_global(cx).context_mode;
+        let context = match context_mode {
+            CodyContextMode::Full => self.context_files_for_completion(buffer, cx),
+            CodyContextMode::FileOnly | CodyContextMode::Off => Vec::new(),
+        };
+        let buffer_id = buffer.entity_id();
+        let buffer = buffer.read(cx);
+        let position = position.to_point_utf16(buffer);
+        let language = buffer.language_at(position);
+        let settings = language_settings(language.as_ref(), buffer.file(), cx);
+        let tab_size = settings.tab_size;
+        let hard_tabs = settings.hard_tabs;
+        let model = language.and_then(|language| {
+            CodySettings::get_global(cx)
+                .model
+                .get(language.name().as_ref())
+                .cloned()
+        });
+        if let Some(model) = &model {
+            if !KNOWN_CODY_MODELS.contains(&model.as_str()) {
+                log::warn!(
+                    "cody: {model:?} (from cody.model) isn't a model this version of Zed \
+                     recognizes; forwarding it to the agent anyway"
+                );
+            }
+        }
+        let relative_path = match context_mode {
+            CodyContextMode::Full | CodyContextMode::FileOnly => buffer
+                .file()
+                .map(|file| file.path().to_path_buf())
+                .unwrap_or_else(|| untitled_relative_path(buffer.language())),
+            CodyContextMode::Off => PathBuf::new(),
+        };
+        let requested_at = Instant::now();
+
+        cx.spawn(|this, mut cx| async move {
+            // `LanguageServer::request` already sends `$/cancelRequest` and stops waiting for a
+            // response when its returned future is dropped, which happens here whenever a newer
+            // keystroke supersedes this request (see `CodyCompletionProvider::refresh`). This
+            // guard just makes sure `pending_completions` is decremented in that case too, not
+            // only on the path below that runs after a response comes back.
+            let _decrement_pending_completions = {
+                let this = this.clone();
+                let mut cx = cx.clone();
+                util::defer(move || {
+                    this.update(&mut cx, |this, _| {
+                        if let CodyServer::Running(server) = &mut this.server {
+                            if let Some(registered_buffer) =
+                                server.registered_buffers.get_mut(&buffer_id)
+                            {
+                                registered_buffer.pending_completions =
+                                    registered_buffer.pending_completions.saturating_sub(1);
+                            }
+                        }
+                    })
+                    .ok();
+                })
+            };
+
+            let result: Result<Vec<Completion>> = async {
+                let (version, snapshot) = snapshot.await?;
+                let lsp_position = point_to_lsp(position);
+                let cache_key = (uri.clone(), lsp_position, version);
+
+                if let Some(completions) = this
+                    .update(&mut cx, |this, _| {
+                        let server = this.server.as_authenticated().log_err()?;
+                        server.completion_cache.get(&cache_key)
+                    })
+                    .ok()
+                    .flatten()
+                {
+                    this.update(&mut cx, |_, cx| {
+                        cx.emit(Event::CompletionsReceived {
+                            count: completions.len(),
+                        })
+                    })
+                    .ok();
+                    return Ok(completions);
+                }
+
+                let result = lsp
+                    .request::<request::GetCompletions>(request::GetCompletionsParams {
+                        doc: request::GetCompletionsDocument {
+                            uri,
+                            tab_size: tab_size.into(),
+                            indent_size: if hard_tabs { 1 } else { tab_size.into() },
+                            insert_spaces: !hard_tabs,
+                            relative_path: relative_path.to_string_lossy().into(),
+                            position: lsp_position,
+                            version: version.try_into().unwrap(),
+                            model,
+                        },
+                        context,
+                        cycling,
+                    })
+                    .await;
+                let result = match result {
+                    Ok(result) => result,
+                    Err(error) => {
+                        if is_rate_limit_error(&error) {
+                            this.update(&mut cx, |this, cx| this.enter_rate_limit_cooldown(cx))
+                                .ok();
+                        } else if is_server_shut_down_error(&error) {
+                            this.update(&mut cx, |this, cx| this.handle_agent_crash(cx)).ok();
+                        }
+                        return Err(error);
+                    }
+                };
+                this.update(&mut cx, |this, _| {
+                    this.rate_limit_backoff = RATE_LIMIT_COOLDOWN_INITIAL;
+                })
+                .ok();
+                let completions: Vec<Completion> = result
+                    .completions
+                    .into_iter()
+                    .map(|completion| {
+                        let start = snapshot
+                            .clip_point_utf16(point_from_lsp(completion.range.start), Bias::Left);
+                        let end =
+                            snapshot.clip_point_utf16(point_from_lsp(completion.range.end), Bias::Left);
+                        Completion {
+                            uuid: completion.uuid,
+                            range: snapshot.anchor_before(start)..snapshot.anchor_after(end),
+                            text: normalize_line_ending(completion.text, snapshot.line_ending()),
+                            is_snippet: completion.insert_text_format
+                                == Some(request::InsertTextFormat::Snippet),
+                            requested_at,
+                        }
+                    })
+                    .collect();
+
+                // The agent sometimes returns multiple completions with identical text but different
+                // uuids, which would otherwise clutter cycling with redundant entries. Keep the first
+                // of each and tell the agent the rest were rejected so its telemetry stays consistent.
+                let mut seen_text = HashSet::default();
+                let mut duplicate_uuids = Vec::new();
+                let mut completions: Vec<Completion> = completions
+                    .into_iter()
+                    .filter(|completion| {
+                        if seen_text.insert(completion.text.clone()) {
+                            true
+                        } else {
+                            duplicate_uuids.push(completion.uuid.clone());
+                            false
+                        }
+                    })
+                    .collect();
+                if !duplicate_uuids.is_empty() {
+                    lsp.request::<request::NotifyRejected>(request::NotifyRejectedParams {
+                        uuids: duplicate_uuids,
+                    })
+                    .await
+                    .log_err();
+                }
+
+                // Cap how many completions are kept from a single request, so a large response
+                // doesn't turn into a noisy cycling list; tell the agent about the dropped ones so
+                // its telemetry doesn't count them as shown.
+                let max_completions = cx.update(|cx| CodySettings::get_global(cx).max_completions)?;
+                if completions.len() > max_completions {
+                    let truncated_uuids = completions
+                        .split_off(max_completions)
+                        .into_iter()
+                        .map(|completion| completion.uuid)
+                        .collect();
+                    lsp.request::<request::NotifyRejected>(request::NotifyRejectedParams {
+                        uuids: truncated_uuids,
+                    })
+                    .await
+                    .log_err();
+                }
+
+                this.update(&mut cx, |this, cx| {
+                    if let Ok(server) = this.server.as_authenticated() {
+                        server.completion_cache.put(cache_key, completions.clone());
+                    }
+                    cx.emit(Event::CompletionsReceived {
+                        count: completions.len(),
+                    });
+                })
+                .ok();
+
+                anyhow::Ok(completions)
+            }
+            .await;
+
+            this.update(&mut cx, |this, _| {
+                if let CodyServer::Running(server) = &mut this.server {
+                    if let Some(registered_buffer) = server.registered_buffers.get_mut(&buffer_id) {
+                        if let Ok(completions) = &result {
+                            registered_buffer.last_completions = completions.clone();
+                        }
+                    }
+                }
+            })
+            .ok();
+
+            result
+        })
+    }
+
+    /// Whether a `completions`/`completions_cycling` request for `buffer` is currently in
+    /// flight. Used by `CodyButton` to show a "thinking" indicator while the agent is working.
+    pub fn has_pending_completion(&self, buffer: &Model<Buffer>) -> bool {
+        self.registered_buffer(buffer)
+            .is_some_and(|buffer| buffer.pending_completions > 0)
+    }
+
+    /// The completions last returned for `buffer`, or empty if none have been requested yet (or
+    /// the buffer isn't registered with the agent).
+    pub fn current_completions(&self, buffer: &Model<Buffer>) -> Vec<Completion> {
+        self.registered_buffer(buffer)
+            .map(|buffer| buffer.last_completions.clone())
+            .unwrap_or_default()
+    }
+
+    /// Like `completions`, but named for callers that want to render every candidate themselves
+    /// (e.g. a picker UI) instead of relying on the default ghost-text flow's notion of a single
+    /// "active" completion. Both just request the same `Vec<Completion>` from the agent and are
+    /// safe to use side by side on the same buffer.
+    pub fn get_completion_candidates<T>(
+        &mut self,
+        buffer: &Model<Buffer>,
+        position: T,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<Completion>>>
+    where
+        T: ToPointUtf16,
+    {
+        self.completions(buffer, position, cx)
+    }
+
+    /// Looks up the candidate at `index` among `buffer`'s most recently returned completions (see
+    /// `current_completions`), for a custom view built on `get_completion_candidates` to resolve
+    /// the user's pick before calling `accept_completion` on it. Returns `None` if `index` is out
+    /// of range of however many candidates were last returned.
+    pub fn select_candidate(&self, buffer: &Model<Buffer>, index: usize) -> Option<Completion> {
+        self.current_completions(buffer).into_iter().nth(index)
+    }
+
+    /// Silences or un-silences completions for `buffer` for the rest of this session, without
+    /// touching the persistent `cody.enabled` setting. Registers `buffer` first if it isn't
+    /// already, so toggling this before the first completion request still takes effect.
+    pub fn toggle_muted_for_buffer(&mut self, buffer: &Model<Buffer>, cx: &mut ModelContext<Self>) {
+        self.register_buffer(buffer, cx);
+        let Ok(server) = self.server.as_authenticated() else {
+            return;
+        };
+        let Some(registered_buffer) = server.registered_buffers.get_mut(&buffer.entity_id())
+        else {
+            return;
+        };
+        registered_buffer.muted = !registered_buffer.muted;
+        cx.notify();
+    }
+
+    /// Whether `ToggleCodyForBuffer` has silenced completions for `buffer`.
+    pub fn is_muted_for_buffer(&self, buffer: &Model<Buffer>) -> bool {
+        self.registered_buffer(buffer)
+            .is_some_and(|buffer| buffer.muted)
+    }
+
+    fn registered_buffer(&self, buffer: &Model<Buffer>) -> Option<&RegisteredBuffer> {
+        match &self.server {
+            CodyServer::Running(server) => server.registered_buffers.get(&buffer.entity_id()),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        match &self.server {
+            CodyServer::Starting { task } => match self.download_progress {
+                Some(DownloadProgress::Determinate(percent)) => Status::Downloading {
+                    percent: Some(percent),
+                },
+                Some(DownloadProgress::Indeterminate) => Status::Downloading { percent: None },
+                None => Status::Starting { task: task.clone() },
+            },
+            CodyServer::Disabled => Status::Disabled,
+            CodyServer::Suspended => Status::Suspended,
+            CodyServer::Error(error) => Status::Error(error.clone()),
+            CodyServer::Running(RunningCodyServer { sign_in_status, .. }) => match sign_in_status {
+                SignInStatus::Authorized { verified: true } => Status::Authorized,
+                SignInStatus::Authorized { verified: false } => Status::Unverified,
+                SignInStatus::Unauthorized { .. } => Status::Unauthorized,
+                SignInStatus::SigningIn { prompt, .. } => Status::SigningIn {
+                    prompt: prompt.clone(),
+                },
+                SignInStatus::SignedOut => Status::SignedOut,
+            },
+        }
+    }
+
+    /// The signed-in account's username, if any, for display alongside `status_text`.
+    pub fn username(&self) -> Option<&str> {
+        match &self.server {
+            CodyServer::Running(RunningCodyServer { username, .. }) => username.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The text of the most recent `Status::Error`, if any, even after the toast that surfaced it
+    /// has been dismissed. Cleared the next time the agent starts successfully. Useful for
+    /// grabbing the exact error text for a bug report.
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    /// A compact, human-readable summary of Cody's current status, suitable for the main status
+    /// bar (as opposed to the fuller detail in the Cody button's own menu).
+    pub fn status_text(&self) -> String {
+        match self.status() {
+            Status::Starting { .. } => "Cody: starting".to_string(),
+            Status::Downloading { percent: Some(percent) } => format!("Cody: downloading {percent}%"),
+            Status::Downloading { percent: None } => "Cody: downloading".to_string(),
+            Status::Disabled => "Cody: disabled".to_string(),
+            Status::Suspended => "Cody: suspended".to_string(),
+            Status::Error(_) => "Cody: error".to_string(),
+            Status::SigningIn { .. } => "Cody: signing in".to_string(),
+            Status::SignedOut => "Cody: signed out".to_string(),
+            Status::Unauthorized => "Cody: unauthorized".to_string(),
+            Status::Authorized => match self.username() {
+                Some(user) => format!("Cody: signed in as {user}"),
+                None => "Cody: signed in".to_string(),
+            },
+            Status::Unverified => match self.username() {
+                Some(user) => format!("Cody: signed in as {user} (verifying...)"),
+                None => "Cody: signed in (verifying...)".to_string(),
+            },
+        }
+    }
+
+    pub fn activity_message(&self) -> Option<Arc<str>> {
+        match &self.server {
+            CodyServer::Running(RunningCodyServer { activity, .. }) => activity.clone(),
+            _ => None,
+        }
+    }
+
+    /// The version of the agent currently running, as reported by its `initialize` response,
+    /// for display next to `cody.agent_version` in the status-bar menu.
+    pub fn agent_version(&self) -> Option<String> {
+        match &self.server {
+            CodyServer::Running(RunningCodyServer { lsp, .. }) => {
+                lsp.version().map(ToOwned::to_owned)
+            }
+            _ => None,
+        }
+    }
+
+    /// The most recent agent log/status messages, oldest first, for the "Show Cody Logs" view.
+    pub fn log_entries(&self) -> &VecDeque<CodyLogEntry> {
+        &self.log_entries
+    }
+
+    /// The number of buffers currently registered with the agent (i.e. that have had a
+    /// `textDocument/didOpen` sent for them), for the "Report Issue" diagnostics report.
+    pub fn registered_buffer_count(&self) -> usize {
+        match &self.server {
+            CodyServer::Running(RunningCodyServer { registered_buffers, .. }) => {
+                registered_buffers.len()
+            }
+            _ => 0,
+        }
+    }
+
+    /// A markdown snapshot of Cody's current state, for attaching to a bug report. Never includes
+    /// the access token: `current_endpoint` is just the Sourcegraph instance URL, the token itself
+    /// is kept separately via `access_token`/credentials storage and never flows through it.
+    pub fn diagnostics_report(&self) -> String {
+        use std::fmt::Write as _;
+
+        let mut report = String::new();
+        writeln!(report, "# Cody Diagnostics").ok();
+        writeln!(report).ok();
+        writeln!(
+            report,
+            "- Agent version: {}",
+            self.agent_version().as_deref().unwrap_or("unknown")
+        )
+        .ok();
+        writeln!(
+            report,
+            "- Endpoint: {} (access token omitted)",
+            self.current_endpoint()
+        )
+        .ok();
+        writeln!(report, "- Status: {}", self.status_text()).ok();
+        writeln!(
+            report,
+            "- Registered buffers: {}",
+            self.registered_buffer_count()
+        )
+        .ok();
+        writeln!(report).ok();
+        writeln!(report, "## Recent Log Entries").ok();
+        writeln!(report).ok();
+        if self.log_entries.is_empty() {
+            writeln!(report, "(none)").ok();
+        } else {
+            for entry in &self.log_entries {
+                writeln!(report, "- [{}] {}", entry.level, entry.message).ok();
+            }
+        }
+        report
+    }
+
+    /// Appends to `log_entries`, dropping the oldest entry once `MAX_LOG_ENTRIES` is exceeded,
+    /// and emits `Event::LogMessage` so an open log view can append live.
+    fn push_log_entry(&mut self, entry: CodyLogEntry, cx: &mut ModelContext<Self>) {
+        if self.log_entries.len() >= MAX_LOG_ENTRIES {
+            self.log_entries.pop_front();
+        }
+        self.log_entries.push_back(entry.clone());
+        cx.emit(Event::LogMessage(entry));
+    }
+
+    fn update_sign_in_status(
+        &mut self,
+        lsp_status: request::SignInStatus,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.buffers.retain(|buffer| buffer.is_upgradable());
+
+        // `MaybeOk` means the agent could only verify the token's shape offline, not that it's
+        // still valid; treat it as provisionally authorized and kick off a network check below
+        // rather than assuming full access.
+        let verified = !matches!(&lsp_status, request::SignInStatus::MaybeOk { .. });
+        let mut needs_verification = false;
+
+        if let Ok(server) = self.server.as_running() {
+            match lsp_status {
+                request::SignInStatus::Ok { user: Some(user) }
+                | request::SignInStatus::MaybeOk { user }
+                | request::SignInStatus::AlreadySignedIn { user } => {
+                    server.sign_in_status = SignInStatus::Authorized { verified };
+                    server.username = Some(user);
+                    needs_verification = !verified;
+                    for buffer in self.buffers.iter().cloned().collect::<Vec<_>>() {
+                        if let Some(buffer) = buffer.upgrade() {
+                            self.register_buffer(&buffer, cx);
+                        }
+                    }
+                }
+                request::SignInStatus::NotAuthorized { user } => {
+                    server.sign_in_status = SignInStatus::Unauthorized;
+                    server.username = Some(user);
+                    server.completion_cache.clear();
+                    // Drop (and thereby cancel) any outstanding accept/reject telemetry from the
+                    // previous account, so it can't still reach the agent once a different
+                    // account signs in on this same session.
+                    server.pending_telemetry.clear();
+                    self.unregister_all_buffers();
+                }
+                request::SignInStatus::Ok { user: None } | request::SignInStatus::NotSignedIn => {
+                    server.sign_in_status = SignInStatus::SignedOut;
+                    server.username = None;
+                    server.completion_cache.clear();
+                    server.pending_telemetry.clear();
+                    self.unregister_all_buffers();
+                }
+            }
+
+            cx.emit(Event::SignInStatusChanged(self.status()));
+            cx.notify();
+        }
+
+        if needs_verification {
+            self.verify_sign_in_status(cx).detach_and_log_err(cx);
+        }
+    }
+
+    /// Re-checks sign-in status against the network (`local_checks_only: false`), downgrading to
+    /// `Unauthorized`/`SignedOut` if it no longer holds. Scheduled after `update_sign_in_status`
+    /// sees `request::SignInStatus::MaybeOk`, which only confirms the token's shape offline.
+    fn verify_sign_in_status(&mut self, cx: &mut ModelContext<Self>) -> Task<Result<()>> {
+        let Ok(server) = self.server.as_running() else {
+            return Task::ready(Ok(()));
+        };
+        let lsp = server.lsp.clone();
+        cx.spawn(|this, mut cx| async move {
+            let status = lsp
+                .request::<request::CheckStatus>(request::CheckStatusParams {
+                    local_checks_only: false,
+                })
+                .await?;
+            this.update(&mut cx, |this, cx| this.update_sign_in_status(status, cx))
+        })
+    }
+}
+
+/// Extracts the contents of the first fenced code block in `text`, or returns `text` unchanged if
+/// it doesn't contain one. Used to pull the generated test out of an otherwise free-form chat
+/// reply.
+fn extract_code_block(text: &str) -> String {
+    let Some(fence_start) = text.find("```") else {
+        return text.to_string();
+    };
+    let after_fence = &text[fence_start + 3..];
+    let content_start = after_fence.find('\n').map_or(0, |index| index + 1);
+    let content = &after_fence[content_start..];
+    match content.find("```") {
+        Some(fence_end) => content[..fence_end].to_string(),
+        None => content.to_string(),
+    }
+}
+
+/// Guesses the project-relative path of the test file that should contain generated tests for
+/// `buffer`, using common per-language test-file naming conventions. Returns `None` when the
+/// buffer has no file, or its language has no file-based test convention recognized here (for
+/// example Rust, whose tests usually live alongside the code in a `#[cfg(test)]` module rather
+/// than a separate file) — callers should fall back to a scratch buffer in that case.
+pub fn test_file_relative_path(buffer: &Buffer) -> Option<PathBuf> {
+    let path = buffer.file()?.path();
+    let extension = path.extension()?.to_str()?;
+    let file_stem = path.file_stem()?.to_str()?;
+    let parent = path.parent().unwrap_or_else(|| Path::new(""));
+    match extension {
+        "ts" | "tsx" | "js" | "jsx" => Some(parent.join(format!("{file_stem}.test.{extension}"))),
+        "py" => Some(parent.join(format!("test_{file_stem}.py"))),
+        "go" => Some(parent.join(format!("{file_stem}_test.go"))),
+        _ => None,
+    }
+}
+
+/// Detects whether `error` is the agent's rate-limit (HTTP 429) response. The agent's JSON-RPC
+/// errors only ever surface as a free-text message (see `lsp::Error`), so this is necessarily a
+/// substring check rather than a structured status code comparison.
+fn is_rate_limit_error(error: &anyhow::Error) -> bool {
+    error.to_string().contains("429")
+}
+
+/// Detects whether `error` is `lsp::LanguageServer::request`'s "server shut down" error, which
+/// it returns once the server's stdout-reading task has ended — whether from an intentional
+/// `shutdown()` or the process having crashed out from under it. There's no way to tell the two
+/// apart from this error alone, but callers only reach it while they still believe the server
+/// is `CodyServer::Running`, which an intentional shutdown always transitions away from first.
+fn is_server_shut_down_error(error: &anyhow::Error) -> bool {
+    error.to_string().contains("server shut down")
+}
+
+/// Rewrites `text`'s line endings to match `line_ending`, so a completion from the agent (which
+/// always uses `\n`) doesn't introduce mixed line endings into a CRLF buffer.
+fn normalize_line_ending(text: String, line_ending: LineEnding) -> String {
+    match line_ending {
+        LineEnding::Unix => text,
+        LineEnding::Windows => text.replace('\n', "\r\n"),
+    }
+}
+
+fn id_for_language(language: Option<&Arc<Language>>) -> String {
+    let language_name = language.map(|language| language.name());
+    match language_name.as_deref() {
+        Some("Plain Text") => "plaintext".to_string(),
+        // These Zed display names don't just lowercase into the identifier the agent expects,
+        // so map the common mismatches explicitly and fall back to lowercasing for the rest.
+        Some("C++") => "cpp".to_string(),
+        Some("C#") => "csharp".to_string(),
+        Some("Objective-C") => "objective-c".to_string(),
+        Some("Shell Script") => "shellscript".to_string(),
+        Some(language_name) => language_name.to_lowercase(),
+        None => "plaintext".to_string(),
+    }
+}
+
+/// Synthesizes a `relative_path` for buffers with no file (untitled/scratch buffers), so the
+/// agent still has something to key its request on and a file extension to infer syntax from.
+fn untitled_relative_path(language: Option<&Arc<Language>>) -> PathBuf {
+    match language.and_then(|language| language.path_suffixes().first()) {
+        Some(extension) => PathBuf::from(format!("untitled.{extension}")),
+        None => PathBuf::from("untitled"),
+    }
+}
+
+fn uri_for_buffer(buffer: &Model<Buffer>, cx: &AppContext) -> lsp::Url {
+    let file = buffer.read(cx).file();
+    if let Some(local_file) = file.and_then(|file| file.as_local()) {
+        let abs_path = local_file.abs_path(cx);
+        let normalized_path = normalize_path_for_uri(&abs_path);
+        if let Ok(uri) = lsp::Url::from_file_path(&normalized_path) {
+            return uri;
+        }
+        log::warn!(
+            "Cody: {abs_path:?} could not be converted to a file:// URI, falling back to a synthetic buffer:// URI"
+        );
+    } else if let Some(file) = file {
+        // Remote buffers (e.g. over SSH) have no local absolute path, but still have a
+        // worktree-relative path, which is enough for the agent to make path-aware decisions
+        // (language inference, ignore rules) without a real file:// URI.
+        if let Ok(mut uri) = lsp::Url::parse(&format!("zed-remote://{}", file.worktree_id())) {
+            uri.set_path(&file.path().to_string_lossy());
+            return uri;
+        }
+    }
+    format!("buffer://{}", buffer.entity_id()).parse().unwrap()
+}
+
+/// Normalizes `path` before it's turned into a `file://` URI, so the same file always produces
+/// the same URI regardless of how its path happened to be cased. On Windows, a drive letter can
+/// come back uppercase or lowercase depending on how the path was originally typed or resolved,
+/// which the Node-based agent treats as referring to two different documents, so completions
+/// requested through one casing never match the `textDocument/didOpen` sent with the other —
+/// uppercasing it here keeps Zed and the agent in agreement.
+#[cfg(windows)]
+fn normalize_path_for_uri(path: &Path) -> PathBuf {
+    let mut bytes = path.to_string_lossy().into_owned().into_bytes();
+    if bytes.get(1) == Some(&b':') {
+        bytes[0] = bytes[0].to_ascii_uppercase();
+    }
+    PathBuf::from(String::from_utf8(bytes).unwrap())
+}
+
+#[cfg(not(windows))]
+fn normalize_path_for_uri(path: &Path) -> &Path {
+    path
+}
+
+fn cody_enabled_for_buffer(buffer: &Model<Buffer>, cx: &AppContext) -> bool {
+    let buffer = buffer.read(cx);
+    let file = buffer.file();
+    let settings = all_language_settings(file, cx);
+    settings.copilot_enabled(buffer.language(), file.map(|f| f.path().as_ref()))
+}
+
+/// Validates an explicit `cody.node_path` override before handing it to the agent, so a typo or
+/// a binary stripped of its executable bit surfaces as a clear error instead of a confusing
+/// process-spawn failure.
+async fn validate_node_path(path: &Path) -> anyhow::Result<()> {
+    let metadata = fs::metadata(path)
+        .await
+        .with_context(|| format!("cody.node_path {path:?} does not exist"))?;
+    if !metadata.is_file() {
+        return Err(anyhow!("cody.node_path {path:?} is not a file"));
+    }
+    #[cfg(not(windows))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            return Err(anyhow!("cody.node_path {path:?} is not executable"));
+        }
+    }
+    Ok(())
+}
+
+async fn clear_cody_dir() {
+    remove_matching(&paths::CODY_DIR, |_| true).await
+}
+
+/// Checks that `name` is a syntactically valid HTTP header field-name (an RFC 7230 `token`:
+/// non-empty, ASCII, and none of the separator characters that would make it ambiguous once
+/// serialized onto the wire), so a typo in `cody.extra_headers` fails fast with a clear error
+/// instead of silently producing a request the auth proxy rejects or a header injection.
+fn validate_header_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(anyhow!("cody.extra_headers has an empty header name"));
+    }
+    let is_valid_tchar = |c: char| {
+        c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+    };
+    if !name.chars().all(is_valid_tchar) {
+        return Err(anyhow!(
+            "cody.extra_headers has an invalid header name {name:?}"
+        ));
+    }
+    Ok(())
+}
+
+async fn get_cody_lsp(
+    http: Arc<dyn HttpClient>,
+    pinned_version: Option<String>,
+    on_progress: &(dyn Fn(Option<u8>) + Send),
+) -> anyhow::Result<PathBuf> {
+    const SERVER_PATH: &str = "dist/agent.js";
+
+    /// Check for the configured (or latest) Cody agent and download it if we haven't already.
+    async fn fetch_latest(
+        http: Arc<dyn HttpClient>,
+        pinned_version: Option<String>,
+        on_progress: &(dyn Fn(Option<u8>) + Send),
+    ) -> anyhow::Result<PathBuf> {
+        let release = match &pinned_version {
+            Some(tag) => github_release_by_tag("sourcegraph/cody", tag, http.clone()).await?,
+            None => latest_github_release("sourcegraph/cody", true, false, http.clone()).await?,
+        };
+
+        let version_dir = &*paths::CODY_DIR.join(format!("cody-{}", release.tag_name));
+
+        fs::create_dir_all(version_dir).await?;
+        let server_path = version_dir.join(SERVER_PATH);
+
+        if fs::metadata(&server_path).await.is_err() {
+            // The Cody agent looks for this dist dir specifically, so lets add it in.
+            let dist_dir = version_dir.join("dist");
+            fs::create_dir_all(dist_dir.as_path()).await?;
+
+            let asset = release
+                .assets
+                .get(0)
+                .context("Github release for the Cody agent contained no assets")?;
+
+            let archive_path = dist_dir.join(&asset.name);
+            let partial_path = dist_dir.join(format!("{}.partial", asset.name));
+
+            // Resume a previous, interrupted download by asking for only the bytes we don't
+            // already have, instead of starting over from zero.
+            let mut downloaded_bytes = fs::metadata(&partial_path).await.map_or(0, |m| m.len());
+
+            let range_header = format!("bytes={downloaded_bytes}-");
+            let headers: &[(&str, &str)] = if downloaded_bytes > 0 {
+                &[("Range", range_header.as_str())]
+            } else {
+                &[]
+            };
+            let mut response = http
+                .get_with_headers(&asset.browser_download_url, headers, true)
+                .await
+                .context("error downloading Cody agent release")?;
+
+            // The server may not support range requests and send the whole file back with a 200
+            // instead of resuming with a 206; restart from scratch rather than append the full
+            // body onto what we already have.
+            if downloaded_bytes > 0 && response.status() != StatusCode::PARTIAL_CONTENT {
+                downloaded_bytes = 0;
+                fs::remove_file(&partial_path).await.log_err();
+            }
+
+            let total_bytes = response
+                .headers()
+                .get("content-length")
+                .and_then(|len| len.to_str().ok())
+                .and_then(|len| len.parse::<u64>().ok())
+                .map(|len| len + downloaded_bytes);
+
+            let mut partial_file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&partial_path)
+                .await?;
+            let mut chunk = [0u8; 64 * 1024];
+            loop {
+                let read = response
+                    .body_mut()
+                    .read(&mut chunk)
+                    .await
+                    .context("error downloading Cody agent release")?;
+                if read == 0 {
+                    break;
+                }
+                partial_file
+                    .write_all(&chunk[..read])
+                    .await
+                    .context("error writing Cody agent release to disk")?;
+                downloaded_bytes += read as u64;
+                on_progress(total_bytes.map(|total_bytes| {
+                    ((downloaded_bytes * 100 / total_bytes.max(1)) as u8).min(100)
+                }));
+            }
+            partial_file.flush().await?;
+            drop(partial_file);
+            fs::rename(&partial_path, &archive_path).await?;
+
+            let archive_bytes = fs::read(&archive_path).await?;
+
+            if let Some(checksum_asset) = release
+                .assets
+                .iter()
+                .find(|other| other.name == format!("{}.sha256", asset.name))
+            {
+                let mut checksum_response = http
+                    .get(&checksum_asset.browser_download_url, Default::default(), true)
+                    .await
+                    .context("error downloading Cody agent checksum")?;
+                let mut checksum_text = String::new();
+                checksum_response
+                    .body_mut()
+                    .read_to_string(&mut checksum_text)
+                    .await
+                    .context("error reading Cody agent checksum")?;
+                let expected_digest = checksum_text
+                    .split_whitespace()
+                    .next()
+                    .context("Cody agent checksum file was empty")?;
+
+                let actual_digest = format!("{:x}", Sha256::digest(&archive_bytes));
+                if !expected_digest.eq_ignore_ascii_case(&actual_digest) {
+                    fs::remove_dir_all(dist_dir.as_path()).await.log_err();
+                    anyhow::bail!(
+                        "checksum mismatch for Cody agent download: expected {expected_digest}, got {actual_digest}"
+                    );
+                }
+            } else {
+                log::warn!(
+                    "no published checksum found for Cody agent release {}; skipping integrity check",
+                    release.tag_name
+                );
+            }
+
+            let decompressed_bytes = GzipDecoder::new(BufReader::new(archive_bytes.as_slice()));
+            let archive = Archive::new(decompressed_bytes);
+            archive.unpack(dist_dir).await?;
+            fs::remove_file(&archive_path).await.log_err();
+
+            // Unlike the old "always latest" behavior, versions are no longer pruned here: with
+            // `cody.agent_version` a user may switch between pinned versions repeatedly, and
+            // each one should stay cached under its own subdirectory instead of being
+            // re-downloaded every time.
+        }
+
+        Ok(server_path)
+    }
+
+    match fetch_latest(http, pinned_version.clone(), on_progress).await {
+        ok @ Result::Ok(..) => ok,
+        e @ Err(..) => {
+            e.log_err();
+            // Fetch a cached binary, if it exists
+            maybe!(async {
+                let last_version_dir = match &pinned_version {
+                    Some(tag) => {
+                        let pinned_dir = paths::CODY_DIR.join(format!("cody-{tag}"));
+                        fs::metadata(&pinned_dir).await.ok().map(|_| pinned_dir)
+                    }
+                    None => {
+                        let mut last_version_dir = None;
+                        let mut entries = fs::read_dir(paths::CODY_DIR.as_path()).await?;
+                        while let Some(entry) = entries.next().await {
+                            let entry = entry?;
+                            if entry.file_type().await?.is_dir() {
+                                last_version_dir = Some(entry.path());
+                            }
+                        }
+                        last_version_dir
+                    }
+                };
+                let last_version_dir =
+                    last_version_dir.ok_or_else(|| anyhow!("no cached binary"))?;
+                let server_path = last_version_dir.join(SERVER_PATH);
+                if server_path.exists() {
+                    Ok(server_path)
+                } else {
+                    Err(anyhow!(
+                        "missing executable in directory {:?}",
+                        last_version_dir
+                    ))
+                }
+            })
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::TestAppContext;
+    use language::{BufferId, LanguageConfig, LanguageMatcher};
+
+    #[gpui::test(iterations = 10)]
+    async fn test_buffer_management(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        let buffer_1 = cx.new_model(|cx| Buffer::local("Hello", cx));
+        let buffer_1_uri: lsp::Url = format!("buffer://{}", buffer_1.entity_id().as_u64())
+            .parse()
+            .unwrap();
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer_1, cx));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+                .await,
+            lsp::DidOpenTextDocumentParams {
+                text_document: lsp::TextDocumentItem::new(
+                    buffer_1_uri.clone(),
+                    "plaintext".into(),
+                    0,
+                    "Hello".into()
+                ),
+            }
+        );
+
+        let buffer_2 = cx.new_model(|cx| Buffer::local("Goodbye", cx));
+        let buffer_2_uri: lsp::Url = format!("buffer://{}", buffer_2.entity_id().as_u64())
+            .parse()
+            .unwrap();
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer_2, cx));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+                .await,
+            lsp::DidOpenTextDocumentParams {
+                text_document: lsp::TextDocumentItem::new(
+                    buffer_2_uri.clone(),
+                    "plaintext".into(),
+                    0,
+                    "Goodbye".into()
+                ),
+            }
+        );
+
+        buffer_1.update(cx, |buffer, cx| buffer.edit([(5..5, " world")], None, cx));
+        cx.executor().advance_clock(Duration::from_millis(100));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidChangeTextDocument>()
+                .await,
+            lsp::DidChangeTextDocumentParams {
+                text_document: lsp::VersionedTextDocumentIdentifier::new(buffer_1_uri.clone(), 1),
+                content_changes: vec![lsp::TextDocumentContentChangeEvent {
+                    range: Some(lsp::Range::new(
+                        lsp::Position::new(0, 5),
+                        lsp::Position::new(0, 5)
+                    )),
+                    range_length: None,
+                    text: " world".into(),
+                }],
+            }
+        );
+
+        // Ensure all previously-registered buffers are closed when signing out.
+        lsp.handle_request::<request::SignOut, _, _>(|_, _| async {
+            Ok(request::SignOutResult {})
+        });
+        cody.update(cx, |cody, cx| cody.sign_out(cx))
+            .await
+            .unwrap();
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidCloseTextDocument>()
+                .await,
+            lsp::DidCloseTextDocumentParams {
+                text_document: lsp::TextDocumentIdentifier::new(buffer_1_uri.clone()),
+            }
+        );
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidCloseTextDocument>()
+                .await,
+            lsp::DidCloseTextDocumentParams {
+                text_document: lsp::TextDocumentIdentifier::new(buffer_2_uri.clone()),
+            }
+        );
+
+        // Ensure all previously-registered buffers are re-opened when signing in.
+        lsp.handle_request::<request::SignInInitiate, _, _>(|_, _| async {
+            Ok(request::SignInInitiateResult::AlreadySignedIn {
+                user: "user-1".into(),
+            })
+        });
+        cody.update(cx, |cody, cx| cody.sign_in(cx))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+                .await,
+            lsp::DidOpenTextDocumentParams {
+                text_document: lsp::TextDocumentItem::new(
+                    buffer_1_uri.clone(),
+                    "plaintext".into(),
+                    0,
+                    "Hello world".into()
+                ),
+            }
+        );
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+                .await,
+            lsp::DidOpenTextDocumentParams {
+                text_document: lsp::TextDocumentItem::new(
+                    buffer_2_uri.clone(),
+                    "plaintext".into(),
+                    0,
+                    "Goodbye".into()
+                ),
+            }
+        );
+
+        let _ = BufferId::new(1);
+    }
+
+    #[gpui::test]
+    async fn test_sign_out_closes_many_buffers_in_one_pass(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        const BUFFER_COUNT: usize = 50;
+        let mut buffers = Vec::with_capacity(BUFFER_COUNT);
+        let mut expected_uris = HashSet::default();
+        for i in 0..BUFFER_COUNT {
+            let buffer = cx.new_model(|cx| Buffer::local(format!("buffer {i}"), cx));
+            let uri: lsp::Url = format!("buffer://{}", buffer.entity_id().as_u64())
+                .parse()
+                .unwrap();
+            expected_uris.insert(uri.clone());
+            cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+            assert_eq!(
+                lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+                    .await
+                    .text_document
+                    .uri,
+                uri
+            );
+            buffers.push(buffer);
+        }
+
+        lsp.handle_request::<request::SignOut, _, _>(|_, _| async { Ok(request::SignOutResult {}) });
+        cody.update(cx, |cody, cx| cody.sign_out(cx))
+            .await
+            .unwrap();
+
+        // The agent is told every buffer is closed, regardless of the (unspecified) order
+        // `registered_buffers` is drained in.
+        let mut closed_uris = HashSet::default();
+        for _ in 0..BUFFER_COUNT {
+            let params = lsp
+                .receive_notification::<lsp::notification::DidCloseTextDocument>()
+                .await;
+            closed_uris.insert(params.text_document.uri);
+        }
+        assert_eq!(closed_uris, expected_uris);
+    }
+
+    #[gpui::test]
+    async fn test_include_text_on_save(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("Hello", cx));
+        let buffer_uri: lsp::Url = format!("buffer://{}", buffer.entity_id().as_u64())
+            .parse()
+            .unwrap();
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+        lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+            .await;
+
+        // By default the saved text isn't sent.
+        buffer.update(cx, |_, cx| cx.emit(language::Event::Saved));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidSaveTextDocument>()
+                .await,
+            lsp::DidSaveTextDocumentParams {
+                text_document: lsp::TextDocumentIdentifier::new(buffer_uri.clone()),
+                text: None,
+            }
+        );
+
+        cx.update(|cx| {
+            cx.update_global(|settings: &mut SettingsStore, cx| {
+                settings.update_user_settings::<CodySettings>(cx, |settings| {
+                    settings.include_text_on_save = Some(true);
+                });
+            })
+        });
+
+        buffer.update(cx, |_, cx| cx.emit(language::Event::Saved));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidSaveTextDocument>()
+                .await,
+            lsp::DidSaveTextDocumentParams {
+                text_document: lsp::TextDocumentIdentifier::new(buffer_uri),
+                text: Some("Hello".into()),
+            }
+        );
+    }
+
+    #[gpui::test]
+    async fn test_private_files_are_not_registered(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| {
+            let mut buffer = Buffer::local("super secret", cx);
+            buffer.file_updated(
+                Arc::new(File {
+                    abs_path: "/root/secrets.txt".into(),
+                    path: Path::new("secrets.txt").into(),
+                    is_private: true,
+                    is_local: true,
+                }),
+                cx,
+            );
+            buffer
+        });
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        // No DidOpenTextDocument should be sent for a private file; sending an
+        // unrelated buffer next and asserting *it* is the next notification received
+        // proves the private one was skipped rather than merely delayed.
+        let other_buffer = cx.new_model(|cx| Buffer::local("not secret", cx));
+        let other_buffer_uri: lsp::Url = format!("buffer://{}", other_buffer.entity_id().as_u64())
+            .parse()
+            .unwrap();
+        cody.update(cx, |cody, cx| cody.register_buffer(&other_buffer, cx));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+                .await,
+            lsp::DidOpenTextDocumentParams {
+                text_document: lsp::TextDocumentItem::new(
+                    other_buffer_uri,
+                    "plaintext".into(),
+                    0,
+                    "not secret".into()
+                ),
+            }
+        );
+    }
+
+    #[gpui::test]
+    async fn test_stale_completion_is_rejected_on_accept(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        let (request_received_tx, request_received_rx) = oneshot::channel();
+        let mut request_received_tx = Some(request_received_tx);
+        lsp.handle_request::<request::GetCompletions, _, _>(move |_, _| {
+            let request_received_tx = request_received_tx.take();
+            async move {
+                if let Some(tx) = request_received_tx {
+                    let _ = tx.send(());
+                }
+                Ok(request::GetCompletionsResult {
+                    completions: vec![request::Completion {
+                        text: "{}".into(),
+                        display_text: "{}".into(),
+                        position: lsp::Position::new(0, 9),
+                        uuid: "completion-1".into(),
+                        range: lsp::Range::new(lsp::Position::new(0, 9), lsp::Position::new(0, 10)),
+                        insert_text_format: None,
+                    }],
+                })
+            }
+        });
+
+        let completions = cody.update(cx, |cody, cx| cody.completions(&buffer, 9, cx));
+
+        // Wait for the agent to receive the request (fixing the snapshot the completion will be
+        // anchored against), then delete exactly the span it covers before the response arrives.
+        request_received_rx.await.unwrap();
+        buffer.update(cx, |buffer, cx| buffer.edit([(8..11, "")], None, cx));
+
+        let completions = completions.await.unwrap();
+        assert_eq!(completions.len(), 1);
+
+        let result = cody
+            .update(cx, |cody, cx| cody.accept_completion(&completions[0], cx))
+            .await;
+        assert!(
+            result.is_err(),
+            "a completion anchored to text deleted before the response arrived should be rejected"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_accept_completion_notifies_agent(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|_, _| async {
+            Ok(request::GetCompletionsResult {
+                completions: vec![request::Completion {
+                    text: "{}".into(),
+                    display_text: "{}".into(),
+                    position: lsp::Position::new(0, 9),
+                    uuid: "completion-1".into(),
+                    range: lsp::Range::new(lsp::Position::new(0, 9), lsp::Position::new(0, 11)),
+                    insert_text_format: None,
+                }],
+            })
+        });
+
+        let completions = cody
+            .update(cx, |cody, cx| cody.completions(&buffer, 9, cx))
+            .await
+            .unwrap();
+        assert_eq!(completions.len(), 1);
+        let completion = &completions[0];
+        assert_eq!(completion.uuid, "completion-1");
+        assert_eq!(completion.text, "{}");
+        assert_eq!(
+            buffer.read_with(cx, |buffer, _| completion.range.to_offset(buffer)),
+            9..11
+        );
+
+        let (accepted_tx, accepted_rx) = oneshot::channel();
+        let mut accepted_tx = Some(accepted_tx);
+        lsp.handle_request::<request::NotifyAccepted, _, _>(move |params, _| {
+            let accepted_tx = accepted_tx.take();
+            async move {
+                if let Some(tx) = accepted_tx {
+                    let _ = tx.send(params.uuid);
+                }
+                Ok(String::new())
+            }
+        });
+
+        cody.update(cx, |cody, cx| cody.accept_completion(completion, cx))
+            .await
+            .unwrap();
+        assert_eq!(accepted_rx.await.unwrap(), "completion-1");
+    }
+
+    #[gpui::test]
+    async fn test_completion_text_matches_buffer_line_ending(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {\r\n}\r\n", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|_, _| async {
+            Ok(request::GetCompletionsResult {
+                completions: vec![request::Completion {
+                    text: "let x = 1;\nlet y = 2;".into(),
+                    display_text: "let x = 1;\nlet y = 2;".into(),
+                    position: lsp::Position::new(0, 11),
+                    uuid: "completion-1".into(),
+                    range: lsp::Range::new(lsp::Position::new(0, 11), lsp::Position::new(0, 11)),
+                    insert_text_format: None,
+                }],
+            })
+        });
+
+        let completions = cody
+            .update(cx, |cody, cx| cody.completions(&buffer, 11, cx))
+            .await
+            .unwrap();
+        assert_eq!(completions[0].text, "let x = 1;\r\nlet y = 2;");
+    }
+
+    #[gpui::test]
+    async fn test_language_change_resets_document_version(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+                .await
+                .text_document
+                .version,
+            0
+        );
+
+        buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "// ")], None, cx));
+        cx.executor().advance_clock(Duration::from_millis(100));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidChangeTextDocument>()
+                .await
+                .text_document
+                .version,
+            1
+        );
+
+        // Switching the buffer's language closes and re-opens the document with the agent.
+        buffer.update(cx, |buffer, cx| {
+            buffer.set_language(
+                Some(Arc::new(Language::new(
+                    LanguageConfig {
+                        name: "Rust".into(),
+                        matcher: LanguageMatcher {
+                            path_suffixes: vec!["rs".into()],
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    None,
+                ))),
+                cx,
+            );
+        });
+        lsp.receive_notification::<lsp::notification::DidCloseTextDocument>()
+            .await;
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+                .await
+                .text_document
+                .version,
+            0,
+            "the re-opened document should start its version counter over at 0"
+        );
+
+        buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "/* */")], None, cx));
+        cx.executor().advance_clock(Duration::from_millis(100));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidChangeTextDocument>()
+                .await
+                .text_document
+                .version,
+            1,
+            "without the fix this would continue from the pre-close version instead of restarting at 0"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_discard_completions_notifies_rejection(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        let completions = vec![
+            Completion {
+                uuid: "completion-1".into(),
+                range: Anchor::MIN..Anchor::MIN,
+                text: "foo".into(),
+                is_snippet: false,
+                requested_at: Instant::now(),
+            },
+            Completion {
+                uuid: "completion-2".into(),
+                range: Anchor::MIN..Anchor::MIN,
+                text: "bar".into(),
+                is_snippet: false,
+                requested_at: Instant::now(),
+            },
+        ];
+
+        let (notified_tx, notified_rx) = oneshot::channel();
+        let mut notified_tx = Some(notified_tx);
+        lsp.handle_request::<request::NotifyRejected, _, _>(move |params, _| {
+            let notified_tx = notified_tx.take();
+            async move {
+                if let Some(tx) = notified_tx {
+                    let _ = tx.send(params);
+                }
+                Ok(String::new())
+            }
+        });
+
+        cody.update(cx, |cody, cx| cody.discard_completions(&completions, cx))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            notified_rx.await.unwrap(),
+            request::NotifyRejectedParams {
+                uuids: vec!["completion-1".into(), "completion-2".into()],
+            }
+        );
+    }
+
+    #[gpui::test]
+    async fn test_status_text_includes_username(cx: &mut TestAppContext) {
+        let (cody, _lsp) = Cody::fake(cx);
+
+        cody.update(cx, |cody, _| assert_eq!(cody.username(), None));
+        assert_eq!(
+            cody.read_with(cx, |cody, _| cody.status_text()),
+            "Cody: signed in"
+        );
+
+        cody.update(cx, |cody, cx| {
+            cody.update_sign_in_status(
+                request::SignInStatus::AlreadySignedIn {
+                    user: "alice".into(),
+                },
+                cx,
+            )
+        });
+
+        cody.update(cx, |cody, _| assert_eq!(cody.username(), Some("alice")));
+        assert_eq!(
+            cody.read_with(cx, |cody, _| cody.status_text()),
+            "Cody: signed in as alice"
+        );
+
+        cody.update(cx, |cody, cx| {
+            cody.update_sign_in_status(request::SignInStatus::NotSignedIn, cx)
+        });
+        cody.update(cx, |cody, _| assert_eq!(cody.username(), None));
+        assert_eq!(
+            cody.read_with(cx, |cody, _| cody.status_text()),
+            "Cody: signed out"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_maybe_ok_is_unverified_until_confirmed(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        cody.update(cx, |cody, cx| {
+            cody.update_sign_in_status(
+                request::SignInStatus::MaybeOk {
+                    user: "alice".into(),
+                },
+                cx,
+            )
+        });
+        cody.read_with(cx, |cody, _| {
+            assert!(matches!(cody.status(), Status::Unverified));
+            assert!(!cody.status().is_authorized());
+            assert_eq!(cody.username(), Some("alice"));
+        });
+
+        lsp.handle_request::<request::CheckStatus, _, _>(|_, _| async {
+            Ok(request::SignInStatus::AlreadySignedIn {
+                user: "alice".into(),
+            })
+        });
+        cx.run_until_parked();
+
+        cody.read_with(cx, |cody, _| {
+            assert!(matches!(cody.status(), Status::Authorized));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_maybe_ok_downgrades_when_verification_fails(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        cody.update(cx, |cody, cx| {
+            cody.update_sign_in_status(
+                request::SignInStatus::MaybeOk {
+                    user: "alice".into(),
+                },
+                cx,
+            )
+        });
+
+        lsp.handle_request::<request::CheckStatus, _, _>(|_, _| async {
+            Ok(request::SignInStatus::NotSignedIn)
+        });
+        cx.run_until_parked();
+
+        cody.read_with(cx, |cody, _| {
+            assert!(matches!(cody.status(), Status::SignedOut));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_status_reports_download_progress(cx: &mut TestAppContext) {
+        let (cody, _lsp) = Cody::fake(cx);
+
+        cody.update(cx, |cody, _| {
+            cody.server = CodyServer::Starting {
+                task: Task::ready(()).shared(),
+            };
+            cody.download_progress = Some(DownloadProgress::Determinate(42));
+        });
+        assert!(matches!(
+            cody.read_with(cx, |cody, _| cody.status()),
+            Status::Downloading { percent: Some(42) }
+        ));
+
+        cody.update(cx, |cody, _| {
+            cody.download_progress = Some(DownloadProgress::Indeterminate);
+        });
+        assert!(matches!(
+            cody.read_with(cx, |cody, _| cody.status()),
+            Status::Downloading { percent: None }
+        ));
+
+        cody.update(cx, |cody, _| {
+            cody.download_progress = None;
+        });
+        assert!(matches!(
+            cody.read_with(cx, |cody, _| cody.status()),
+            Status::Starting { .. }
+        ));
+    }
+
+    #[test]
+    fn test_id_for_language_mismatches() {
+        fn id_for(name: &str) -> String {
+            id_for_language(Some(&Arc::new(Language::new(
+                LanguageConfig {
+                    name: name.into(),
+                    ..Default::default()
+                },
+                None,
+            ))))
+        }
+
+        assert_eq!(id_for("C++"), "cpp");
+        assert_eq!(id_for("C#"), "csharp");
+        assert_eq!(id_for("Objective-C"), "objective-c");
+        assert_eq!(id_for("Shell Script"), "shellscript");
+        assert_eq!(id_for("Rust"), "rust");
+        assert_eq!(id_for_language(None), "plaintext");
+    }
+
+    #[test]
+    fn test_validate_header_name() {
+        assert!(validate_header_name("X-Auth-Proxy-Token").is_ok());
+        assert!(validate_header_name("X-Custom_Header.1").is_ok());
+
+        assert!(validate_header_name("").is_err());
+        assert!(validate_header_name("Invalid Header").is_err());
+        assert!(validate_header_name("Invalid:Header").is_err());
+        assert!(validate_header_name("Invalid\r\nHeader").is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_normalize_path_for_uri_uppercases_drive_letter() {
+        assert_eq!(
+            normalize_path_for_uri(Path::new(r"c:\Users\foo\bar.rs")),
+            PathBuf::from(r"C:\Users\foo\bar.rs")
+        );
+        assert_eq!(
+            normalize_path_for_uri(Path::new(r"C:\Users\foo\bar.rs")),
+            PathBuf::from(r"C:\Users\foo\bar.rs")
+        );
+    }
+
+    #[gpui::test]
+    async fn test_completions_oneshot_unregisters_unregistered_buffer(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        let buffer_uri: lsp::Url = format!("buffer://{}", buffer.entity_id().as_u64())
+            .parse()
+            .unwrap();
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|_, _| async {
+            Ok(request::GetCompletionsResult {
+                completions: Vec::new(),
+            })
+        });
+
+        let completions = cody.update(cx, |cody, cx| cody.completions_oneshot(&buffer, 9, cx));
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+                .await
+                .text_document
+                .uri,
+            buffer_uri
+        );
+        completions.await.unwrap();
+
+        assert_eq!(
+            lsp.receive_notification::<lsp::notification::DidCloseTextDocument>()
+                .await,
+            lsp::DidCloseTextDocumentParams {
+                text_document: lsp::TextDocumentIdentifier::new(buffer_uri),
+            }
+        );
+        cody.update(cx, |cody, _| {
+            assert!(!cody.buffers.contains(&buffer.downgrade()));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_completions_oneshot_leaves_registered_buffer_open(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+        lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+            .await;
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|_, _| async {
+            Ok(request::GetCompletionsResult {
+                completions: Vec::new(),
+            })
+        });
+
+        cody.update(cx, |cody, cx| cody.completions_oneshot(&buffer, 9, cx))
+            .await
+            .unwrap();
+
+        // The buffer was already registered independently, so `completions_oneshot` must not
+        // close it out from under whatever registered it.
+        cody.update(cx, |cody, _| {
+            assert!(cody.buffers.contains(&buffer.downgrade()));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_switch_account_pushes_configuration_without_restart(cx: &mut TestAppContext) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        let new_endpoint = "https://example.sourcegraph.com";
+        cx.update(|cx| Cody::set_access_token(new_endpoint, "new-token".into(), cx))
+            .await
+            .unwrap();
+        cody.update(cx, |cody, _| {
+            cody.known_accounts.push(new_endpoint.to_string());
+        });
+
+        lsp.handle_request::<request::CheckStatus, _, _>(|_, _| async {
+            Ok(request::SignInStatus::Ok {
+                user: Some("new-user".into()),
+            })
+        });
+
+        cody.update(cx, |cody, cx| {
+            cody.switch_account(new_endpoint.to_string(), cx)
+        })
+        .await;
+
+        let params = lsp
+            .receive_notification::<lsp::notification::DidChangeConfiguration>()
+            .await;
+        let settings: request::ExtensionConfiguration =
+            serde_json::from_value(params.settings).unwrap();
+        assert_eq!(settings.server_endpoint, new_endpoint);
+        assert_eq!(settings.access_token, "new-token");
+
+        // The agent process (and thus the fake server's request handler registrations) is never
+        // torn down and recreated by a `switch_account` call.
+        cody.read_with(cx, |cody, _| {
+            assert_eq!(cody.username(), Some("new-user"));
+        });
+    }
+
+    #[gpui::test]
+    async fn test_max_completions_truncates_and_rejects(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        cx.update(|cx| {
+            cx.update_global(|settings: &mut SettingsStore, cx| {
+                settings.update_user_settings::<CodySettings>(cx, |settings| {
+                    settings.max_completions = Some(1);
+                });
+            })
+        });
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|_, _| async {
+            Ok(request::GetCompletionsResult {
+                completions: vec![
+                    request::Completion {
+                        text: "{}".into(),
+                        display_text: "{}".into(),
+                        position: lsp::Position::new(0, 9),
+                        uuid: "completion-1".into(),
+                        range: lsp::Range::new(lsp::Position::new(0, 9), lsp::Position::new(0, 10)),
+                        insert_text_format: None,
+                    },
+                    request::Completion {
+                        text: "{ return 1; }".into(),
+                        display_text: "{ return 1; }".into(),
+                        position: lsp::Position::new(0, 9),
+                        uuid: "completion-2".into(),
+                        range: lsp::Range::new(lsp::Position::new(0, 9), lsp::Position::new(0, 10)),
+                        insert_text_format: None,
+                    },
+                ],
+            })
+        });
+
+        let (rejected_tx, rejected_rx) = oneshot::channel();
+        let mut rejected_tx = Some(rejected_tx);
+        lsp.handle_request::<request::NotifyRejected, _, _>(move |params, _| {
+            let rejected_tx = rejected_tx.take();
+            async move {
+                if let Some(tx) = rejected_tx {
+                    let _ = tx.send(params);
+                }
+                Ok(String::new())
+            }
+        });
+
+        let completions = cody
+            .update(cx, |cody, cx| cody.completions(&buffer, 9, cx))
+            .await
+            .unwrap();
+        assert_eq!(completions.len(), 1);
+        assert_eq!(completions[0].uuid, "completion-1");
+
+        assert_eq!(
+            rejected_rx.await.unwrap(),
+            request::NotifyRejectedParams {
+                uuids: vec!["completion-2".into()],
+            }
+        );
+    }
+
+    #[gpui::test]
+    async fn test_context_mode_off_omits_relative_path_and_context(cx: &mut TestAppContext) {
+        cx.update(|cx| {
+            cx.update_global(|settings: &mut SettingsStore, cx| {
+                settings.update_user_settings::<CodySettings>(cx, |settings| {
+                    settings
+                        .context
+                        .get_or_insert_with(Default::default)
+                        .include_open_files = Some(true);
+                    settings.context_mode = Some(CodyContextMode::Off);
+                });
+            })
+        });
+
+        let (cody, lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|params, _| async move {
+            assert_eq!(params.doc.relative_path, "");
+            assert!(params.context.is_empty());
+            Ok(request::GetCompletionsResult {
+                completions: Vec::new(),
+            })
+        });
+
+        cody.update(cx, |cody, cx| cody.completions(&buffer, 9, cx))
+            .await
+            .unwrap();
+    }
+
+    #[gpui::test]
+    async fn test_has_pending_completion_and_current_completions(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        assert!(!cody.read_with(cx, |cody, _| cody.has_pending_completion(&buffer)));
+        assert_eq!(
+            cody.read_with(cx, |cody, _| cody.current_completions(&buffer)),
+            Vec::new()
+        );
+
+        let (request_received_tx, request_received_rx) = oneshot::channel();
+        let mut request_received_tx = Some(request_received_tx);
+        lsp.handle_request::<request::GetCompletions, _, _>(move |_, _| {
+            let request_received_tx = request_received_tx.take();
+            async move {
+                if let Some(tx) = request_received_tx {
+                    let _ = tx.send(());
+                }
+                Ok(request::GetCompletionsResult {
+                    completions: vec![request::Completion {
+                        text: "{}".into(),
+                        display_text: "{}".into(),
+                        position: lsp::Position::new(0, 9),
+                        uuid: "completion-1".into(),
+                        range: lsp::Range::new(lsp::Position::new(0, 9), lsp::Position::new(0, 10)),
+                        insert_text_format: None,
+                    }],
+                })
+            }
+        });
+
+        let completions = cody.update(cx, |cody, cx| cody.completions(&buffer, 9, cx));
+
+        request_received_rx.await.unwrap();
+        assert!(cody.read_with(cx, |cody, _| cody.has_pending_completion(&buffer)));
+
+        let completions = completions.await.unwrap();
+        assert!(!cody.read_with(cx, |cody, _| cody.has_pending_completion(&buffer)));
+        assert_eq!(
+            cody.read_with(cx, |cody, _| cody.current_completions(&buffer)),
+            completions
+        );
+    }
+
+    #[gpui::test]
+    async fn test_pending_completion_cleared_when_request_is_cancelled(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        let (request_received_tx, request_received_rx) = oneshot::channel();
+        let mut request_received_tx = Some(request_received_tx);
+        lsp.handle_request::<request::GetCompletions, _, _>(move |_, _| {
+            let request_received_tx = request_received_tx.take();
+            async move {
+                if let Some(tx) = request_received_tx {
+                    let _ = tx.send(());
+                }
+                // Never resolves: this test cancels the request before the agent replies.
+                std::future::pending::<Result<request::GetCompletionsResult>>().await
+            }
+        });
+
+        let completions = cody.update(cx, |cody, cx| cody.completions(&buffer, 9, cx));
+        request_received_rx.await.unwrap();
+        assert!(cody.read_with(cx, |cody, _| cody.has_pending_completion(&buffer)));
+
+        // Dropping the task before it resolves is what happens when a newer keystroke
+        // supersedes it (see `CodyCompletionProvider::refresh`); `pending_completions` must still
+        // be decremented, not just on the path that runs after a response comes back.
+        drop(completions);
+        cx.run_until_parked();
+        assert!(!cody.read_with(cx, |cody, _| cody.has_pending_completion(&buffer)));
+    }
+
+    #[gpui::test]
+    async fn test_agent_crash_triggers_restart(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|_, _| async {
+            Err(anyhow!("server shut down"))
+        });
+
+        cody.update(cx, |cody, cx| cody.completions(&buffer, 9, cx))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            cody.read_with(cx, |cody, _| cody.status()),
+            Status::Starting { .. }
+        ));
+    }
+
+    #[gpui::test]
+    async fn test_completions_in_untitled_buffer(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| {
+            let mut buffer = Buffer::local("fn foo() {}", cx);
+            buffer.set_language(
+                Some(Arc::new(Language::new(
+                    LanguageConfig {
+                        name: "Rust".into(),
+                        matcher: LanguageMatcher {
+                            path_suffixes: vec!["rs".into()],
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    None,
+                ))),
+                cx,
+            );
+            buffer
+        });
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|params, _| async move {
+            assert_eq!(params.doc.relative_path, "untitled.rs");
+            Ok(request::GetCompletionsResult {
+                completions: Vec::new(),
+            })
+        });
+
+        cody.update(cx, |cody, cx| cody.completions(&buffer, 9, cx))
+            .await
+            .unwrap();
+    }
+
+    #[gpui::test]
+    async fn test_toggle_muted_for_buffer(cx: &mut TestAppContext) {
+        let (cody, lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+        cody.update(cx, |cody, cx| cody.register_buffer(&buffer, cx));
+
+        assert!(!cody.read_with(cx, |cody, _| cody.is_muted_for_buffer(&buffer)));
+
+        cody.update(cx, |cody, cx| cody.toggle_muted_for_buffer(&buffer, cx));
+        assert!(cody.read_with(cx, |cody, _| cody.is_muted_for_buffer(&buffer)));
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|_, _| async move {
+            panic!("the agent should not be queried while the buffer is muted");
+        });
+
+        let completions = cody
+            .update(cx, |cody, cx| cody.completions(&buffer, 9, cx))
+            .await
+            .unwrap();
+        assert_eq!(completions, Vec::new());
+
+        cody.update(cx, |cody, cx| cody.toggle_muted_for_buffer(&buffer, cx));
+        assert!(!cody.read_with(cx, |cody, _| cody.is_muted_for_buffer(&buffer)));
+    }
+
+    #[gpui::test]
+    async fn test_uri_for_buffer_falls_back_on_unconvertible_path(cx: &mut TestAppContext) {
+        // `lsp::Url::from_file_path` rejects non-absolute paths, which should never happen in
+        // practice, but a malformed `LocalFile::abs_path` shouldn't be able to panic Zed.
+        let buffer = cx.new_model(|cx| {
+            let mut buffer = Buffer::local("fn foo() {}", cx);
+            buffer.file_updated(
+                Arc::new(File {
+                    abs_path: "not-absolute.rs".into(),
+                    path: Path::new("not-absolute.rs").into(),
+                    is_private: false,
+                    is_local: true,
+                }),
+                cx,
+            );
+            buffer
+        });
+
+        let uri = cx.update(|cx| uri_for_buffer(&buffer, cx));
+        assert_eq!(uri.scheme(), "buffer");
+    }
+
+    #[gpui::test]
+    async fn test_uri_for_buffer_uses_worktree_relative_path_for_remote_buffers(
+        cx: &mut TestAppContext,
+    ) {
+        let buffer = cx.new_model(|cx| {
+            let mut buffer = Buffer::local("fn foo() {}", cx);
+            buffer.file_updated(
+                Arc::new(File {
+                    abs_path: PathBuf::new(),
+                    path: Path::new("src/main.rs").into(),
+                    is_private: false,
+                    is_local: false,
+                }),
+                cx,
+            );
+            buffer
+        });
+
+        let uri = cx.update(|cx| uri_for_buffer(&buffer, cx));
+        assert_eq!(uri.scheme(), "zed-remote");
+        assert_eq!(uri.path(), "/src/main.rs");
+    }
+
+    #[gpui::test]
+    async fn test_register_buffer_is_idempotent_for_concurrent_completions(
+        cx: &mut TestAppContext,
+    ) {
+        let (cody, mut lsp) = Cody::fake(cx);
+
+        let buffer = cx.new_model(|cx| Buffer::local("fn foo() {}", cx));
+
+        lsp.handle_request::<request::GetCompletions, _, _>(|_, _| async {
+            Ok(request::GetCompletionsResult {
+                completions: Vec::new(),
+            })
+        });
+
+        // Two `completions` calls on the same freshly-created buffer, issued back to back before
+        // either has a chance to run any of its async continuation, must still only register
+        // (and open) the buffer once: `register_buffer` always runs synchronously to completion
+        // before control returns to the caller, so the second call's `entry().or_insert_with`
+        // always sees the first call's entry already in place.
+        let first = cody.update(cx, |cody, cx| cody.completions(&buffer, 9, cx));
+        let second = cody.update(cx, |cody, cx| cody.completions(&buffer, 9, cx));
+
+        lsp.receive_notification::<lsp::notification::DidOpenTextDocument>()
+            .await;
+
+        first.await.unwrap();
+        second.await.unwrap();
+
+        cody.update(cx, |cody, _| {
+            let server = cody.server.as_authenticated().unwrap();
+            assert_eq!(server.registered_buffers.len(), 1);
+        });
+    }
+
+    struct File {
+        abs_path: PathBuf,
+        path: Arc<Path>,
+        is_private: bool,
+        is_local: bool,
+    }
+
+    impl language::File for File {
+        fn as_local(&self) -> Option<&dyn language::LocalFile> {
+            self.is_local.then_some(self as &dyn language::LocalFile)
+        }
+
+        fn mtime(&self) -> Option<std::time::SystemTime> {
+            unimplemented!()
+        }
+
+        fn path(&self) -> &Arc<Path> {
+            &self.path
+        }
+
+        fn full_path(&self, _: &AppContext) -> PathBuf {
+            unimplemented!()
+        }
+
+        fn file_name<'a>(&'a self, _: &'a AppContext) -> &'a std::ffi::OsStr {
+            unimplemented!()
+        }
+
+        fn is_deleted(&self) -> bool {
+            unimplemented!()
+        }
+
+        fn as_any(&self) -> &dyn std::any::Any {
+            unimplemented!()
+        }
+
+        fn to_proto(&self) -> rpc::proto::File {
+            unimplemented!()
+        }
+
+        fn worktree_id(&self) -> usize {
+            0
+        }
+
+        fn is_private(&self) -> bool {
+            self.is_private
+        }
+    }
+
+    impl language::LocalFile for File {
+        fn abs_path(&self, _: &AppContext) -> PathBuf {
+            self.abs_path.clone()
+        }
+
+        fn load(&self, _: &AppContext) -> Task<Result<String>> {
+            unimplemented!()
+        }
+
+        fn buffer_reloaded(
+            &self,
+            _: BufferId,
+            _: &clock::Global,
+            _: language::LineEnding,
+            _: Option<std::time::SystemTime>,
+            _: &mut AppContext,
+        ) {
+            unimplemented!()
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Every field added to `CodySettings` must be mirrored in `CodySettingsContent` as an `Option`,
+//! documented with a `/// Default: ...` line, and able to derive `JsonSchema` (directly or via
+//! its own type deriving it), so Zed's settings JSON editor can autocomplete and validate it.
+//! `SettingsStore` picks this up automatically through `Settings::FileContent`'s `JsonSchema`
+//! bound once `CodySettings::register` runs -- no separate schema registration is needed.
+
+use anyhow::Result;
+use collections::HashMap;
+use gpui::AppContext;
+use std::sync::Arc;
+use schemars::JsonSchema;
+use serde_derive::{Deserialize, Serialize};
+use settings::{Settings, SettingsSources};
+
+#[derive(Deserialize, Debug)]
+pub struct CodySettings {
+    pub enabled: bool,
+    pub trace: bool,
+    pub log_level: String,
+    pub max_file_size: u64,
+    pub completion_cache_size: usize,
+    pub max_completions: usize,
+    pub node_path: Option<String>,
+    pub idle_timeout: Option<u64>,
+    pub agent_version: Option<String>,
+    pub include_text_on_save: bool,
+    pub debounce: CodyDebounceMode,
+    pub context_mode: CodyContextMode,
+    pub completion_display: CodyCompletionDisplay,
+    pub context: CodyContextSettings,
+    pub extra_headers: HashMap<String, String>,
+    pub trigger_characters: HashMap<Arc<str>, Vec<String>>,
+    pub model: HashMap<Arc<str>, String>,
+    pub disable_in_comments: bool,
+    pub disable_in_strings: bool,
+}
+
+/// How long to wait after a keystroke before requesting completions.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CodyDebounceMode {
+    /// Always wait the same fixed amount of time.
+    #[default]
+    Fixed,
+    /// Wait longer while the user is typing rapidly, and request immediately once they pause.
+    Adaptive,
+}
+
+/// Controls what's sent to the agent alongside a completion request's cursor position, for
+/// privacy-sensitive repos where even the file's relative path or other open buffers shouldn't
+/// leave the machine.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CodyContextMode {
+    /// Send the file's relative path, plus other open buffers if `cody.context.include_open_files`
+    /// is enabled.
+    #[default]
+    Full,
+    /// Send the file's relative path, but never other open buffers, regardless of
+    /// `cody.context.include_open_files`.
+    FileOnly,
+    /// Send neither the file's relative path nor other open buffers — only the buffer's own
+    /// content (already known to the agent via `textDocument/didOpen`) and the cursor position.
+    Off,
+}
+
+/// How an active completion is shown in the buffer.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CodyCompletionDisplay {
+    /// Show only the active completion, as gray ghost text inserted at the cursor.
+    #[default]
+    Inline,
+    /// Show every returned completion as a numbered list in the ghost text, with the active one
+    /// marked, so cycling through alternatives doesn't require accepting one to see the others.
+    Popup,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CodyContextSettings {
+    pub include_open_files: bool,
+    pub max_bytes: usize,
+}
+
+/// Configuration for the Cody agent.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct CodySettingsContent {
+    /// Whether to run the Cody agent at all. Independent of Copilot's own enablement setting —
+    /// toggling one does not affect the other.
+    ///
+    /// Default: true
+    pub enabled: Option<bool>,
+    /// Whether to have the Cody agent write a trace of its JSON-RPC traffic to a file under
+    /// Zed's logs directory. Useful when debugging the agent itself.
+    ///
+    /// Default: false
+    pub trace: Option<bool>,
+    /// The minimum level of log messages from the Cody agent to forward to Zed's log
+    /// ("trace", "debug", "info", "warn", "error", or "off").
+    ///
+    /// Default: "info"
+    pub log_level: Option<String>,
+    /// The maximum size, in bytes, of a file that will be registered with the Cody agent.
+    /// Files larger than this are skipped to avoid slow, oversized `DidOpenTextDocument`/
+    /// `DidChangeTextDocument` payloads.
+    ///
+    /// Default: 1000000
+    pub max_file_size: Option<u64>,
+    /// The number of completion requests to keep cached, keyed by buffer URI, cursor position,
+    /// and buffer version, so re-requesting the same completion doesn't hit the agent again.
+    ///
+    /// Default: 50
+    pub completion_cache_size: Option<usize>,
+    /// The maximum number of completions to keep from a single completion request. Any beyond
+    /// this are dropped (and reported to the agent via `notifyRejected`) before the result ever
+    /// reaches cycling or display, so a large response doesn't turn into a noisy cycling list.
+    ///
+    /// Default: 3
+    pub max_completions: Option<usize>,
+    /// Overrides the node binary used to run the Cody agent, instead of the one Zed would
+    /// otherwise download and manage via its bundled node runtime. Useful when corporate
+    /// policy requires a specific node binary, or to avoid the download entirely. Must point
+    /// to an existing, executable file.
+    ///
+    /// Default: null
+    pub node_path: Option<String>,
+    /// The number of seconds of inactivity (no completion requests or buffer registrations)
+    /// after which the Cody agent process is shut down to free up memory. It's restarted
+    /// automatically the next time it's needed. Set to null to never shut it down.
+    ///
+    /// Default: null
+    pub idle_timeout: Option<u64>,
+    /// Pins the Cody agent to an exact release tag (e.g. "1.2.3") instead of always using the
+    /// latest one. Each pinned version is downloaded into its own subdirectory under Zed's Cody
+    /// data directory, so switching this setting back and forth doesn't require re-downloading.
+    ///
+    /// Default: null
+    pub agent_version: Option<String>,
+    /// Whether to include the full buffer text in the `textDocument/didSave` notification sent
+    /// to the Cody agent when a file is saved. Some agent indexing relies on the saved content
+    /// being present rather than re-reading the file from disk itself.
+    ///
+    /// Default: false
+    pub include_text_on_save: Option<bool>,
+    /// Whether to wait a fixed amount of time after each keystroke before requesting
+    /// completions ("fixed"), or to grow that wait the faster the user is typing and shrink it
+    /// to zero once they pause ("adaptive"), so bursts of typing don't each trigger a request.
+    ///
+    /// Default: "fixed"
+    pub debounce: Option<CodyDebounceMode>,
+    /// Controls what's sent to the agent alongside a completion request's cursor position:
+    /// "full" sends the file's relative path and, if enabled, other open buffers; "file_only"
+    /// sends the relative path but never other open buffers; "off" sends neither, for
+    /// privacy-sensitive repos where even a file path shouldn't leave the machine.
+    ///
+    /// Default: "full"
+    pub context_mode: Option<CodyContextMode>,
+    /// How an active completion is shown: "inline" shows only the active completion as ghost
+    /// text; "popup" shows every returned completion as a numbered list in the ghost text, with
+    /// the active one marked, so cycling through alternatives (`cody::NextSuggestion` /
+    /// `PreviousSuggestion`) doesn't require accepting one to see the others.
+    ///
+    /// Default: "inline"
+    pub completion_display: Option<CodyCompletionDisplay>,
+    /// Settings for what extra context is sent along with a completion request.
+    pub context: Option<CodyContextSettingsContent>,
+    /// Extra HTTP headers to send with every request the agent makes to the Sourcegraph
+    /// endpoint, for Enterprise deployments that sit behind an auth proxy requiring them (e.g.
+    /// `{"X-Auth-Proxy-Token": "..."}`). Header names must be valid HTTP token characters;
+    /// invalid ones prevent the agent from starting.
+    ///
+    /// Default: {}
+    pub extra_headers: Option<HashMap<String, String>>,
+    /// Per-language characters that must be the one just typed for a completion request to be
+    /// sent automatically. Languages with no entry here request on every keystroke, which is the
+    /// previous and still-default behavior. Regardless of this setting, the `cody::Suggest`
+    /// action always requests a completion immediately.
+    ///
+    /// Default: {}
+    pub trigger_characters: Option<HashMap<Arc<str>, Vec<String>>>,
+    /// Per-language overrides for which Cody model to generate completions with, keyed by
+    /// language name (e.g. "Rust", "SQL") as it appears in Zed's language registry. Forwarded to
+    /// the agent with each completion request for a buffer in that language. Names it doesn't
+    /// recognize are logged as a warning but still forwarded, since new models can roll out on
+    /// the agent side before Zed knows their names.
+    ///
+    /// Default: {}
+    pub model: Option<HashMap<Arc<str>, String>>,
+    /// Whether to skip requesting a completion when the cursor is inside a comment, per the
+    /// buffer's tree-sitter syntax tree. Completing inside comments is often unhelpful, since
+    /// there's no code context to complete against.
+    ///
+    /// Default: false
+    pub disable_in_comments: Option<bool>,
+    /// Whether to skip requesting a completion when the cursor is inside a string literal, per
+    /// the buffer's tree-sitter syntax tree.
+    ///
+    /// Default: false
+    pub disable_in_strings: Option<bool>,
+}
+
+/// Settings for what extra context is sent along with a completion request.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct CodyContextSettingsContent {
+    /// Whether to include a handful of other recently-edited open buffers, along with the one
+    /// being completed, as extra context for the completion request. Buffers are still subject
+    /// to `disabled_globs` and private-file exclusion, same as the buffer being completed.
+    ///
+    /// Default: false
+    pub include_open_files: Option<bool>,
+    /// The maximum total size, in bytes, of the other open buffers' content sent as context for
+    /// a single completion request.
+    ///
+    /// Default: 10000
+    pub max_bytes: Option<usize>,
+}
+
+impl Settings for CodySettings {
+    const KEY: Option<&'static str> = Some("cody");
+
+    type FileContent = CodySettingsContent;
+
+    fn load(sources: SettingsSources<Self::FileContent>, _: &mut AppContext) -> Result<Self> {
+        sources.json_merge()
+    }
+}
@@ -0,0 +1,392 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtensionConfiguration {
+    pub access_token: String,
+    pub server_endpoint: String,
+}
+
+pub enum CheckStatus {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckStatusParams {
+    pub local_checks_only: bool,
+}
+
+impl lsp::request::Request for CheckStatus {
+    type Params = CheckStatusParams;
+    type Result = SignInStatus;
+    const METHOD: &'static str = "checkStatus";
+}
+
+pub enum SignInInitiate {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignInInitiateParams {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status")]
+pub enum SignInInitiateResult {
+    AlreadySignedIn { user: String },
+    PromptUserDeviceFlow(PromptUserDeviceFlow),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptUserDeviceFlow {
+    pub user_code: String,
+    pub verification_uri: String,
+}
+
+impl lsp::request::Request for SignInInitiate {
+    type Params = SignInInitiateParams;
+    type Result = SignInInitiateResult;
+    const METHOD: &'static str = "signInInitiate";
+}
+
+pub enum SignInConfirm {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignInConfirmParams {
+    pub user_code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "status")]
+pub enum SignInStatus {
+    #[serde(rename = "OK")]
+    Ok {
+        user: Option<String>,
+    },
+    MaybeOk {
+        user: String,
+    },
+    AlreadySignedIn {
+        user: String,
+    },
+    NotAuthorized {
+        user: String,
+    },
+    NotSignedIn,
+}
+
+impl lsp::request::Request for SignInConfirm {
+    type Params = SignInConfirmParams;
+    type Result = SignInStatus;
+    const METHOD: &'static str = "signInConfirm";
+}
+
+pub enum SignOut {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignOutParams {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignOutResult {}
+
+impl lsp::request::Request for SignOut {
+    type Params = SignOutParams;
+    type Result = SignOutResult;
+    const METHOD: &'static str = "signOut";
+}
+
+pub enum GetCompletions {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetCompletionsParams {
+    pub doc: GetCompletionsDocument,
+    /// A handful of other recently-edited open buffers, sent when `cody.context.include_open_files`
+    /// is enabled, so the agent can use them as extra context for the completion.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<GetCompletionsContextFile>,
+    /// Whether this request is cycling through alternatives for a position a completion was
+    /// already requested for, rather than an initial request. Forwarded to the agent so it can
+    /// return a different candidate than whatever it already returned, instead of a fresh
+    /// "primary" completion.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cycling: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetCompletionsContextFile {
+    pub uri: lsp::Url,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetCompletionsDocument {
+    pub tab_size: u32,
+    pub indent_size: u32,
+    pub insert_spaces: bool,
+    pub uri: lsp::Url,
+    pub relative_path: String,
+    pub position: lsp::Position,
+    pub version: usize,
+    /// Overrides which model the agent uses to generate this completion, per `cody.model`.
+    /// Absent means let the agent pick its own default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetCompletionsResult {
+    pub completions: Vec<Completion>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Completion {
+    pub text: String,
+    pub position: lsp::Position,
+    pub uuid: String,
+    pub range: lsp::Range,
+    pub display_text: String,
+    /// Set when `text` is a snippet (e.g. containing `$0`-style tabstops) rather than plain text.
+    /// Absent from older agent versions, in which case the completion is treated as plain text.
+    #[serde(default)]
+    pub insert_text_format: Option<InsertTextFormat>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum InsertTextFormat {
+    PlainText,
+    Snippet,
+}
+
+impl lsp::request::Request for GetCompletions {
+    type Params = GetCompletionsParams;
+    type Result = GetCompletionsResult;
+    const METHOD: &'static str = "autocomplete/execute";
+}
+
+pub enum CompletionChunk {}
+
+/// Sent by the agent while it streams a longer completion in, one or more times per request,
+/// before the final `GetCompletionsResult` response. `text` is the full
+/// completion text accumulated so far, not just the newly streamed delta, so a listener can
+/// simply replace whatever it's currently displaying with `text`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionChunkParams {
+    pub uuid: String,
+    pub text: String,
+}
+
+impl lsp::notification::Notification for CompletionChunk {
+    type Params = CompletionChunkParams;
+    const METHOD: &'static str = "completions/chunk";
+}
+
+pub enum ChatSubmitMessage {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSubmitMessageParams {
+    pub id: String,
+    pub message: ChatMessage,
+    /// Ids of the Enterprise remote repositories (see `Repos`) chat should scope its answer to.
+    /// Empty means the agent falls back to its own default scope.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub repos: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub speaker: ChatSpeaker,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatSpeaker {
+    Human,
+    Assistant,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatSubmitMessageResult {
+    #[serde(rename = "type")]
+    pub message_type: String,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl lsp::request::Request for ChatSubmitMessage {
+    type Params = ChatSubmitMessageParams;
+    type Result = ChatSubmitMessageResult;
+    const METHOD: &'static str = "chat/submitMessage";
+}
+
+pub enum Repos {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReposParams {
+    /// A fuzzy name query (e.g. "github.com/acme/"), used by the agent to search the Enterprise
+    /// instance's list of remote repositories.
+    pub query: String,
+    pub first: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Repo {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReposResult {
+    pub repos: Vec<Repo>,
+}
+
+impl lsp::request::Request for Repos {
+    type Params = ReposParams;
+    type Result = ReposResult;
+    const METHOD: &'static str = "graphql/getRepoIds";
+}
+
+pub enum EditCommand {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditCommandParams {
+    pub uri: lsp::Url,
+    pub range: lsp::Range,
+    pub instruction: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditCommandResult {
+    pub new_text: String,
+}
+
+impl lsp::request::Request for EditCommand {
+    type Params = EditCommandParams;
+    type Result = EditCommandResult;
+    const METHOD: &'static str = "commands/edit";
+}
+
+pub enum LogMessage {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogMessageParams {
+    pub level: u8,
+    pub message: String,
+    pub metadata_str: String,
+    pub extra: Vec<String>,
+}
+
+impl lsp::notification::Notification for LogMessage {
+    type Params = LogMessageParams;
+    const METHOD: &'static str = "LogMessage";
+}
+
+pub enum StatusNotification {}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusNotificationParams {
+    pub message: String,
+    pub status: String, // One of Normal/InProgress
+}
+
+impl lsp::notification::Notification for StatusNotification {
+    type Params = StatusNotificationParams;
+    const METHOD: &'static str = "statusNotification";
+}
+
+pub enum SetEditorInfo {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetEditorInfoParams {
+    pub editor_info: EditorInfo,
+    pub editor_plugin_info: EditorPluginInfo,
+}
+
+impl lsp::request::Request for SetEditorInfo {
+    type Params = SetEditorInfoParams;
+    type Result = String;
+    const METHOD: &'static str = "setEditorInfo";
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditorInfo {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditorPluginInfo {
+    pub name: String,
+    pub version: String,
+}
+
+pub enum NotifyAccepted {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyAcceptedParams {
+    pub uuid: String,
+}
+
+impl lsp::request::Request for NotifyAccepted {
+    type Params = NotifyAcceptedParams;
+    type Result = String;
+    const METHOD: &'static str = "notifyAccepted";
+}
+
+/// Same request as `NotifyAccepted`, under the method name used by agent releases at or above
+/// `cody::ACCEPT_COMPLETION_METHOD_V2_MIN_VERSION`.
+pub enum NotifyAcceptedV2 {}
+
+impl lsp::request::Request for NotifyAcceptedV2 {
+    type Params = NotifyAcceptedParams;
+    type Result = String;
+    const METHOD: &'static str = "autocomplete/completions/accepted";
+}
+
+pub enum NotifyPartialAccept {}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyPartialAcceptParams {
+    pub uuid: String,
+    pub accepted_length: usize,
+}
+
+impl lsp::request::Request for NotifyPartialAccept {
+    type Params = NotifyPartialAcceptParams;
+    type Result = String;
+    const METHOD: &'static str = "notifyPartialAcceptance";
+}
+
+pub enum NotifyRejected {}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NotifyRejectedParams {
+    pub uuids: Vec<String>,
+}
+
+impl lsp::request::Request for NotifyRejected {
+    type Params = NotifyRejectedParams;
+    type Result = String;
+    const METHOD: &'static str = "notifyRejected";
+}
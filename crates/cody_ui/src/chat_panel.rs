@@ -0,0 +1,216 @@
+use cody::{
+    request::{ChatMessage, Repo},
+    Cody,
+};
+use db::kvp::KEY_VALUE_STORE;
+use editor::Editor;
+use gpui::{
+    div, rems, AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView,
+    InteractiveElement, IntoElement, Model, ParentElement, Render, Styled, View, ViewContext,
+};
+use ui::{prelude::*, Button, IconButton, IconName, Label};
+use util::ResultExt;
+use workspace::{ModalView, WorkspaceId};
+
+/// The key `selected_repos` are persisted under, one entry per workspace so each workspace can
+/// scope chat to its own set of remote repositories.
+fn repos_kvp_key(workspace_id: WorkspaceId) -> String {
+    format!("cody-chat-repos-{workspace_id:?}")
+}
+
+pub struct CodyChatPanel {
+    cody: Model<Cody>,
+    workspace_id: WorkspaceId,
+    id: String,
+    messages: Vec<ChatMessage>,
+    message_editor: View<Editor>,
+    repo_query_editor: View<Editor>,
+    repo_results: Vec<Repo>,
+    focus_handle: FocusHandle,
+}
+
+impl FocusableView for CodyChatPanel {
+    fn focus_handle(&self, _: &AppContext) -> gpui::FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl EventEmitter<DismissEvent> for CodyChatPanel {}
+impl ModalView for CodyChatPanel {}
+
+impl CodyChatPanel {
+    pub fn new(cody: Model<Cody>, workspace_id: WorkspaceId, cx: &mut ViewContext<Self>) -> Self {
+        let message_editor = cx.new_view(|cx| {
+            let mut editor = Editor::single_line(cx);
+            editor.set_placeholder_text("Ask Cody…", cx);
+            editor
+        });
+        let repo_query_editor = cx.new_view(|cx| {
+            let mut editor = Editor::single_line(cx);
+            editor.set_placeholder_text("Search remote repositories…", cx);
+            editor
+        });
+
+        cx.spawn(|this, mut cx| async move {
+            let repos = cx
+                .background_executor()
+                .spawn(async move { KEY_VALUE_STORE.read_kvp(&repos_kvp_key(workspace_id)) })
+                .await
+                .log_err()
+                .flatten()
+                .and_then(|state| serde_json::from_str::<Vec<Repo>>(&state).log_err())
+                .unwrap_or_default();
+            if repos.is_empty() {
+                return;
+            }
+            this.update(&mut cx, |this, cx| {
+                this.cody.update(cx, |cody, cx| cody.set_selected_repos(repos, cx));
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+
+        Self {
+            cody,
+            workspace_id,
+            id: "zed-cody-chat".into(),
+            messages: Vec::new(),
+            message_editor,
+            repo_query_editor,
+            repo_results: Vec::new(),
+            focus_handle: cx.focus_handle(),
+        }
+    }
+
+    fn send(&mut self, cx: &mut ViewContext<Self>) {
+        let text = self.message_editor.read(cx).text(cx).trim().to_string();
+        if text.is_empty() {
+            return;
+        }
+        self.message_editor
+            .update(cx, |editor, cx| editor.set_text("", cx));
+
+        let id = self.id.clone();
+        let task = self.cody.update(cx, |cody, cx| cody.chat(id, text, cx));
+        cx.spawn(|this, mut cx| async move {
+            let messages = task.await?;
+            this.update(&mut cx, |this, cx| {
+                this.messages = messages;
+                cx.notify();
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn search_repos(&mut self, cx: &mut ViewContext<Self>) {
+        let query = self.repo_query_editor.read(cx).text(cx).trim().to_string();
+        if query.is_empty() {
+            self.repo_results.clear();
+            cx.notify();
+            return;
+        }
+        let task = self.cody.update(cx, |cody, cx| cody.search_repos(query, cx));
+        cx.spawn(|this, mut cx| async move {
+            let repos = task.await?;
+            this.update(&mut cx, |this, cx| {
+                this.repo_results = repos;
+                cx.notify();
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    fn toggle_repo(&mut self, repo: Repo, cx: &mut ViewContext<Self>) {
+        let mut selected = self.cody.read(cx).selected_repos().to_vec();
+        if let Some(index) = selected.iter().position(|selected| selected.id == repo.id) {
+            selected.remove(index);
+        } else {
+            selected.push(repo);
+        }
+        self.cody
+            .update(cx, |cody, cx| cody.set_selected_repos(selected.clone(), cx));
+        self.persist_selected_repos(selected, cx);
+        cx.notify();
+    }
+
+    fn persist_selected_repos(&self, repos: Vec<Repo>, cx: &mut ViewContext<Self>) {
+        let workspace_id = self.workspace_id;
+        cx.background_executor()
+            .spawn(async move {
+                let state = serde_json::to_string(&repos)?;
+                KEY_VALUE_STORE
+                    .write_kvp(repos_kvp_key(workspace_id), state)
+                    .await
+            })
+            .detach_and_log_err(cx);
+    }
+}
+
+impl Render for CodyChatPanel {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let selected_repos = self.cody.read(cx).selected_repos().to_vec();
+
+        v_flex()
+            .id("cody-chat-panel")
+            .track_focus(&self.focus_handle)
+            .elevation_3(cx)
+            .w_96()
+            .p_4()
+            .gap_2()
+            .child(Headline::new("Cody Chat").size(HeadlineSize::Small))
+            .child(
+                v_flex()
+                    .gap_1()
+                    .max_h(rems(16.))
+                    .children(self.messages.iter().map(|message| {
+                        Label::new(format!("{:?}: {}", message.speaker, message.text))
+                    })),
+            )
+            .child(div().w_full().child(self.message_editor.clone()))
+            .child(
+                Button::new("cody-chat-send", "Send")
+                    .full_width()
+                    .on_click(cx.listener(|this, _, cx| this.send(cx))),
+            )
+            .child(Label::new("Scope to repositories").size(LabelSize::Small))
+            .children((!selected_repos.is_empty()).then(|| {
+                h_flex().flex_wrap().gap_1().children(selected_repos.into_iter().map(|repo| {
+                    h_flex()
+                        .gap_1()
+                        .px_1()
+                        .rounded_md()
+                        .bg(cx.theme().colors().element_background)
+                        .child(Label::new(repo.name.clone()).size(LabelSize::Small))
+                        .child(
+                            IconButton::new(
+                                SharedString::from(format!("cody-repo-remove-{}", repo.id)),
+                                IconName::Close,
+                            )
+                            .icon_size(IconSize::XSmall)
+                            .on_click(cx.listener(move |this, _, cx| {
+                                this.toggle_repo(repo.clone(), cx)
+                            })),
+                        )
+                }))
+            }))
+            .child(div().w_full().child(self.repo_query_editor.clone()))
+            .child(
+                Button::new("cody-chat-repo-search", "Search")
+                    .full_width()
+                    .on_click(cx.listener(|this, _, cx| this.search_repos(cx))),
+            )
+            .children((!self.repo_results.is_empty()).then(|| {
+                v_flex().gap_1().max_h(rems(10.)).children(self.repo_results.clone().into_iter().map(
+                    |repo| {
+                        let label = repo.name.clone();
+                        Button::new(SharedString::from(format!("cody-repo-{}", repo.id)), label)
+                            .full_width()
+                            .on_click(cx.listener(move |this, _, cx| {
+                                this.toggle_repo(repo.clone(), cx)
+                            }))
+                    },
+                ))
+            }))
+    }
+}
@@ -0,0 +1,647 @@
+use crate::chat_panel::CodyChatPanel;
+use crate::log_view::CodyLogView;
+use crate::sign_in::CodyCodeVerification;
+use anyhow::{Context, Result};
+use cody::{Cody, CodySettings, OpenChat, Restart, Status, SwitchAccount, ToggleCodyForBuffer};
+use editor::{scroll::Autoscroll, Editor};
+use fs::Fs;
+use gpui::{
+    div, percentage, AnchorCorner, Animation, AnimationExt, AppContext, AsyncWindowContext,
+    ClipboardItem, Entity, InteractiveElement, IntoElement, Model, ParentElement, Render,
+    SharedString, Subscription, Transformation, View, ViewContext, WeakView, WindowContext,
+};
+use language::{
+    language_settings::{all_language_settings, AllLanguageSettings},
+    Buffer, File, Language,
+};
+use settings::{initial_local_settings_content, Settings, SettingsStore};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use util::{paths, ResultExt};
+use workspace::notifications::NotificationId;
+use workspace::{
+    create_and_open_local_file,
+    item::ItemHandle,
+    ui::{
+        popover_menu, ButtonCommon, Clickable, ContextMenu, Icon, IconButton, IconName, IconSize,
+        Tooltip,
+    },
+    StatusItemView, Toast, Workspace,
+};
+use zed_actions::OpenBrowser;
+
+const CODY_UPGRADE_URL: &str = "https://sourcegraph.com/cody";
+
+struct CodyStartingToast;
+
+struct CodyErrorToast;
+
+pub struct CodyButton {
+    editor_subscription: Option<(Subscription, usize)>,
+    language: Option<Arc<Language>>,
+    file: Option<Arc<dyn File>>,
+    file_too_large: bool,
+    buffer: Option<Model<Buffer>>,
+    fs: Arc<dyn Fs>,
+}
+
+impl Render for CodyButton {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let Some(cody) = Cody::global(cx) else {
+            return div();
+        };
+        let status = cody.read(cx).status();
+        let activity = cody.read(cx).activity_message();
+
+        let muted = self
+            .buffer
+            .as_ref()
+            .is_some_and(|buffer| cody.read(cx).is_muted_for_buffer(buffer));
+
+        let icon = match status {
+            Status::Error(_) => IconName::CodyError,
+            Status::Authorized if muted => IconName::CodyDisabled,
+            Status::Authorized => IconName::Cody,
+            Status::Unauthorized => IconName::ExclamationTriangle,
+            _ => IconName::CodyInit,
+        };
+
+        if let Some(activity) = activity {
+            return div()
+                .child(
+                    Icon::new(IconName::ArrowCircle)
+                        .size(IconSize::Small)
+                        .with_animation(
+                            "cody-activity-spin",
+                            Animation::new(Duration::from_secs(2)).repeat(),
+                            |icon, delta| icon.transform(Transformation::rotate(percentage(delta))),
+                        ),
+                )
+                .tooltip(move |cx| Tooltip::text(activity.clone(), cx));
+        }
+
+        if let Status::Downloading { percent } = status {
+            let tooltip_text = match percent {
+                Some(percent) => format!("Downloading Cody update... {percent}%"),
+                None => "Downloading Cody update...".to_string(),
+            };
+            return div()
+                .child(
+                    Icon::new(IconName::ArrowCircle)
+                        .size(IconSize::Small)
+                        .with_animation(
+                            "cody-download-spin",
+                            Animation::new(Duration::from_secs(2)).repeat(),
+                            |icon, delta| icon.transform(Transformation::rotate(percentage(delta))),
+                        ),
+                )
+                .tooltip(move |cx| Tooltip::text(tooltip_text.clone(), cx));
+        }
+
+        if cody.read(cx).rate_limited_for().is_some() {
+            let cody = cody.clone();
+            return div().child(
+                IconButton::new("cody-rate-limited", IconName::CodyInit)
+                    .icon_size(IconSize::Small)
+                    .tooltip(move |cx| {
+                        let cooldown = cody.read(cx).rate_limited_for().unwrap_or_default();
+                        Tooltip::text(
+                            format!("Cody rate limited, retrying in {}s", cooldown.as_secs()),
+                            cx,
+                        )
+                    }),
+            );
+        }
+
+        if let Status::Error(e) = status {
+            return div().child(
+                IconButton::new("cody-error", icon)
+                    .icon_size(IconSize::Small)
+                    .on_click(cx.listener(move |_, _, cx| {
+                        if let Some(workspace) = cx.window_handle().downcast::<Workspace>() {
+                            workspace
+                                .update(cx, |workspace, cx| {
+                                    workspace.show_toast(
+                                        Toast::new(
+                                            NotificationId::unique::<CodyErrorToast>(),
+                                            format!("Cody can't be started: {}", e),
+                                        )
+                                        .on_click("Reinstall Cody", |cx| {
+                                            if let Some(cody) = Cody::global(cx) {
+                                                cody.update(cx, |cody, cx| cody.reinstall(cx))
+                                                    .detach();
+                                            }
+                                        }),
+                                        cx,
+                                    );
+                                })
+                                .ok();
+                        }
+                    }))
+                    .tooltip(|cx| Tooltip::text("Cody", cx)),
+            );
+        }
+        let this = cx.view().clone();
+        let tooltip: SharedString = if self.file_too_large {
+            "Cody is disabled for this file because it's larger than cody.max_file_size".into()
+        } else if muted {
+            "Cody completions are muted for this buffer".into()
+        } else {
+            match status {
+                Status::Unauthorized => "Your account doesn't have Cody access".into(),
+                _ => "Cody".into(),
+            }
+        };
+
+        div().child(
+            popover_menu("cody")
+                .menu(move |cx| match status {
+                    Status::Authorized => {
+                        Some(this.update(cx, |this, cx| this.build_cody_menu(cx)))
+                    }
+                    Status::Unauthorized => {
+                        Some(this.update(cx, |this, cx| this.build_cody_unauthorized_menu(cx)))
+                    }
+                    _ => Some(this.update(cx, |this, cx| this.build_cody_start_menu(cx))),
+                })
+                .anchor(AnchorCorner::BottomRight)
+                .trigger(
+                    IconButton::new("cody-icon", icon)
+                        .tooltip(move |cx| Tooltip::text(tooltip.clone(), cx)),
+                ),
+        )
+    }
+}
+
+impl CodyButton {
+    pub fn new(fs: Arc<dyn Fs>, cx: &mut ViewContext<Self>) -> Self {
+        if let Some(cody) = Cody::global(cx) {
+            cx.observe(&cody, |_, _, cx| cx.notify()).detach()
+        }
+
+        cx.observe_global::<SettingsStore>(move |_, cx| cx.notify())
+            .detach();
+
+        Self {
+            editor_subscription: None,
+            language: None,
+            file: None,
+            file_too_large: false,
+            buffer: None,
+            fs,
+        }
+    }
+
+    pub fn build_cody_start_menu(&mut self, cx: &mut ViewContext<Self>) -> View<ContextMenu> {
+        let last_error =
+            Cody::global(cx).and_then(|cody| cody.read(cx).last_error().map(Arc::from));
+        ContextMenu::build(cx, move |mut menu, _| {
+            menu = menu.entry("Sign In", None, initiate_sign_in);
+            if let Some(last_error) = last_error.clone() {
+                menu = menu.entry("Copy Last Error", None, move |cx| {
+                    cx.write_to_clipboard(ClipboardItem::new(String::from(&*last_error)));
+                });
+            }
+            menu
+        })
+    }
+
+    pub fn build_cody_menu(&mut self, cx: &mut ViewContext<Self>) -> View<ContextMenu> {
+        let Some(cody) = Cody::global(cx) else {
+            return ContextMenu::build(cx, |menu, _| menu);
+        };
+        let current_endpoint = cody.read(cx).current_endpoint().to_string();
+        let known_accounts = cody.read(cx).known_accounts().to_vec();
+        let agent_version = cody.read(cx).agent_version();
+        let username = cody.read(cx).username().map(ToOwned::to_owned);
+        let extension = self
+            .file
+            .as_ref()
+            .and_then(|file| file.path().extension())
+            .and_then(|extension| extension.to_str())
+            .map(ToOwned::to_owned);
+        let workspace_toggle_label = match self.file.as_ref() {
+            Some(file) if !all_language_settings(Some(file), cx).copilot_enabled_for_path(file.path()) => {
+                "Enable Cody for This Workspace"
+            }
+            _ => "Disable Cody for This Workspace",
+        };
+        let muted_for_buffer = self
+            .buffer
+            .as_ref()
+            .is_some_and(|buffer| cody.read(cx).is_muted_for_buffer(buffer));
+
+        ContextMenu::build(cx, move |mut menu, _| {
+            if let Some(username) = username.clone() {
+                menu = menu.header(format!("Signed in as {username}"));
+            }
+
+            menu = menu.action("Open Chat", OpenChat.boxed_clone());
+
+            if let Some(extension) = extension.clone() {
+                menu = menu.entry(
+                    format!("Disable Cody for .{} Files", extension),
+                    None,
+                    move |cx| {
+                        if let Some(workspace) = cx.window_handle().downcast::<Workspace>() {
+                            if let Ok(workspace) = workspace.root_view(cx) {
+                                let workspace = workspace.downgrade();
+                                let glob = format!("**/*.{}", extension);
+                                cx.spawn(|cx| disable_cody_for_glob(workspace, glob, cx))
+                                    .detach_and_log_err(cx);
+                            }
+                        }
+                    },
+                );
+            }
+
+            menu = menu.entry(workspace_toggle_label, None, move |cx| {
+                if let Some(workspace) = cx.window_handle().downcast::<Workspace>() {
+                    if let Ok(workspace) = workspace.root_view(cx) {
+                        let workspace = workspace.downgrade();
+                        cx.spawn(|cx| toggle_cody_for_workspace(workspace, cx))
+                            .detach_and_log_err(cx);
+                    }
+                }
+            });
+
+            menu = menu.toggleable_entry(
+                "Mute Cody for This Buffer",
+                muted_for_buffer,
+                None,
+                |cx| cx.dispatch_action(ToggleCodyForBuffer.boxed_clone()),
+            );
+
+            if known_accounts.len() > 1 {
+                menu = menu.header("Accounts");
+                for endpoint in known_accounts {
+                    let is_active = endpoint == current_endpoint;
+                    menu = menu.toggleable_entry(endpoint.clone(), is_active, None, {
+                        let endpoint = endpoint.clone();
+                        move |cx| {
+                            cx.dispatch_action(
+                                SwitchAccount {
+                                    endpoint: endpoint.clone(),
+                                }
+                                .boxed_clone(),
+                            );
+                        }
+                    });
+                }
+                menu = menu.separator();
+            }
+
+            if let Some(version) = agent_version {
+                menu = menu.header(format!("Version {version}"));
+            }
+
+            menu.entry("Show Cody Logs", None, open_logs)
+                .entry("Report Issue", None, report_issue)
+                .action("Restart Cody", Restart.boxed_clone())
+                .entry("Sign Out", None, sign_out)
+        })
+    }
+
+    pub fn build_cody_unauthorized_menu(&mut self, cx: &mut ViewContext<Self>) -> View<ContextMenu> {
+        ContextMenu::build(cx, move |menu, _| {
+            menu.link(
+                "Upgrade to use Cody",
+                OpenBrowser {
+                    url: CODY_UPGRADE_URL.to_string(),
+                }
+                .boxed_clone(),
+            )
+            .entry("Sign Out", None, sign_out)
+        })
+    }
+
+    pub fn update_enabled(&mut self, editor: View<Editor>, cx: &mut ViewContext<Self>) {
+        let editor = editor.read(cx);
+        let snapshot = editor.buffer().read(cx).snapshot(cx);
+        let suggestion_anchor = editor.selections.newest_anchor().start;
+        let language = snapshot.language_at(suggestion_anchor);
+        let file = snapshot.file_at(suggestion_anchor).cloned();
+        self.language = language.cloned();
+        self.file = file;
+        self.buffer = editor.buffer().read(cx).as_singleton();
+        self.file_too_large =
+            snapshot.len() as u64 > CodySettings::get_global(cx).max_file_size;
+
+        // Touch the global settings so this view re-renders on any settings change.
+        let _ = all_language_settings(self.file.as_ref(), cx);
+
+        cx.notify()
+    }
+}
+
+impl StatusItemView for CodyButton {
+    fn set_active_pane_item(&mut self, item: Option<&dyn ItemHandle>, cx: &mut ViewContext<Self>) {
+        if let Some(editor) = item.and_then(|item| item.act_as::<Editor>(cx)) {
+            self.editor_subscription = Some((
+                cx.observe(&editor, Self::update_enabled),
+                editor.entity_id().as_u64() as usize,
+            ));
+            self.update_enabled(editor, cx);
+        } else {
+            self.language = None;
+            self.buffer = None;
+            self.editor_subscription = None;
+        }
+        cx.notify();
+    }
+}
+
+/// Appends `glob` to the same `copilot.disabled_globs` setting Cody's enablement check
+/// piggybacks on (see `cody_enabled_for_buffer` in the `cody` crate), using the same
+/// settings-edit machinery as Copilot's own "Hide Suggestions for This Path" entry.
+async fn disable_cody_for_glob(
+    workspace: WeakView<Workspace>,
+    glob: String,
+    mut cx: AsyncWindowContext,
+) -> Result<()> {
+    let settings_editor = workspace
+        .update(&mut cx, |_, cx| {
+            create_and_open_local_file(&paths::SETTINGS, cx, || {
+                settings::initial_user_settings_content().as_ref().into()
+            })
+        })?
+        .await?
+        .downcast::<Editor>()
+        .unwrap();
+
+    settings_editor.downgrade().update(&mut cx, |item, cx| {
+        let text = item.buffer().read(cx).snapshot(cx).text();
+
+        let settings = cx.global::<SettingsStore>();
+        let edits = settings.edits_for_update::<AllLanguageSettings>(&text, |file| {
+            let copilot = file.copilot.get_or_insert_with(Default::default);
+            let globs = copilot.disabled_globs.get_or_insert_with(|| {
+                settings
+                    .get::<AllLanguageSettings>(None)
+                    .copilot
+                    .disabled_globs
+                    .iter()
+                    .map(|glob| glob.glob().to_string())
+                    .collect()
+            });
+            globs.push(glob.clone());
+        });
+
+        if !edits.is_empty() {
+            item.change_selections(Some(Autoscroll::newest()), cx, |selections| {
+                selections.select_ranges(edits.iter().map(|e| e.0.clone()));
+            });
+            item.edit(edits.iter().cloned(), cx);
+        }
+    })?;
+
+    Ok(())
+}
+
+/// Adds or removes a `**/*` entry in `copilot.disabled_globs` in the current project's local
+/// `.zed/settings.json`, the same setting `disable_cody_for_glob` edits in the global user
+/// settings file, so the toggle only affects this workspace's worktree rather than every project.
+async fn toggle_cody_for_workspace(
+    workspace: WeakView<Workspace>,
+    mut cx: AsyncWindowContext,
+) -> Result<()> {
+    const DISABLE_EVERYTHING_GLOB: &str = "**/*";
+
+    let project = workspace.update(&mut cx, |workspace, _| workspace.project().clone())?;
+    let worktree = project
+        .update(&mut cx, |project, cx| {
+            project
+                .visible_worktrees(cx)
+                .find_map(|tree| tree.read(cx).root_entry()?.is_dir().then_some(tree))
+        })?
+        .context("the current workspace has no worktree to write local settings to")?;
+    let worktree_id = worktree.update(&mut cx, |worktree, _| worktree.id())?;
+
+    let settings_path: &Path = &paths::LOCAL_SETTINGS_RELATIVE_PATH;
+    if let Some(dir_path) = settings_path.parent() {
+        if worktree.update(&mut cx, |tree, _| tree.entry_for_path(dir_path).is_none())? {
+            project
+                .update(&mut cx, |project, cx| {
+                    project.create_entry((worktree_id, dir_path), true, cx)
+                })?
+                .await
+                .context("worktree was removed")?;
+        }
+    }
+    if worktree.update(&mut cx, |tree, _| tree.entry_for_path(settings_path).is_none())? {
+        project
+            .update(&mut cx, |project, cx| {
+                project.create_entry((worktree_id, settings_path), false, cx)
+            })?
+            .await
+            .context("worktree was removed")?;
+    }
+
+    let settings_editor = workspace
+        .update(&mut cx, |workspace, cx| {
+            workspace.open_path((worktree_id, settings_path), None, true, cx)
+        })?
+        .await?
+        .downcast::<Editor>()
+        .context("unexpected item type: expected editor item")?;
+
+    settings_editor.downgrade().update(&mut cx, |item, cx| {
+        if let Some(buffer) = item.buffer().read(cx).as_singleton() {
+            if buffer.read(cx).is_empty() {
+                buffer.update(cx, |buffer, cx| {
+                    buffer.edit([(0..0, initial_local_settings_content())], None, cx)
+                });
+            }
+        }
+
+        let text = item.buffer().read(cx).snapshot(cx).text();
+        let settings = cx.global::<SettingsStore>();
+        let edits = settings.edits_for_update::<AllLanguageSettings>(&text, |file| {
+            let copilot = file.copilot.get_or_insert_with(Default::default);
+            let globs = copilot.disabled_globs.get_or_insert_with(Vec::new);
+            if let Some(index) = globs.iter().position(|glob| glob == DISABLE_EVERYTHING_GLOB) {
+                globs.remove(index);
+            } else {
+                globs.push(DISABLE_EVERYTHING_GLOB.to_string());
+            }
+        });
+
+        if !edits.is_empty() {
+            item.change_selections(Some(Autoscroll::newest()), cx, |selections| {
+                selections.select_ranges(edits.iter().map(|e| e.0.clone()));
+            });
+            item.edit(edits.iter().cloned(), cx);
+        }
+    })?;
+
+    Ok(())
+}
+
+pub fn open_chat(cx: &mut WindowContext) {
+    let Some(cody) = Cody::global(cx) else {
+        return;
+    };
+    let Some(workspace) = cx.window_handle().downcast::<Workspace>() else {
+        return;
+    };
+    workspace
+        .update(cx, |workspace, cx| {
+            let workspace_id = workspace.database_id();
+            workspace.toggle_modal(cx, |cx| CodyChatPanel::new(cody, workspace_id, cx));
+        })
+        .log_err();
+}
+
+/// Signs out of Cody, showing an error toast (and leaving the account signed in) if the
+/// `SignOut` request to the agent fails rather than optimistically reporting signed-out.
+pub fn sign_out(cx: &mut WindowContext) {
+    let Some(cody) = Cody::global(cx) else {
+        return;
+    };
+    let Some(workspace) = cx.window_handle().downcast::<Workspace>() else {
+        return;
+    };
+    let task = cody.update(cx, |cody, cx| cody.sign_out(cx));
+    cx.spawn(|mut cx| async move {
+        if let Err(error) = task.await {
+            workspace
+                .update(&mut cx, |workspace, cx| {
+                    workspace.show_toast(
+                        Toast::new(
+                            NotificationId::unique::<CodyErrorToast>(),
+                            format!("Cody couldn't sign out: {}", error),
+                        ),
+                        cx,
+                    );
+                })
+                .log_err();
+        }
+    })
+    .detach();
+}
+
+pub fn open_logs(cx: &mut WindowContext) {
+    let Some(cody) = Cody::global(cx) else {
+        return;
+    };
+    let Some(workspace) = cx.window_handle().downcast::<Workspace>() else {
+        return;
+    };
+    workspace
+        .update(cx, |workspace, cx| {
+            workspace.toggle_modal(cx, |cx| CodyLogView::new(cody, cx));
+        })
+        .log_err();
+}
+
+/// Opens a new unsaved buffer containing a markdown report of Cody's current state (agent
+/// version, endpoint, status, recent log entries, registered-buffer count), for attaching to a
+/// GitHub issue. Never includes the access token -- see `Cody::diagnostics_report`.
+pub fn report_issue(cx: &mut WindowContext) {
+    let Some(cody) = Cody::global(cx) else {
+        return;
+    };
+    let Some(workspace) = cx.window_handle().downcast::<Workspace>() else {
+        return;
+    };
+    let report = cody.read(cx).diagnostics_report();
+    let project = workspace
+        .update(cx, |workspace, _| workspace.project().clone())
+        .log_err();
+    let Some(project) = project else {
+        return;
+    };
+
+    cx.spawn(|mut cx| async move {
+        let buffer = project
+            .update(&mut cx, |project, cx| project.create_buffer(&report, None, cx))??;
+        workspace.update(&mut cx, |workspace, cx| {
+            workspace.add_item_to_active_pane(
+                Box::new(cx.new_view(|cx| Editor::for_buffer(buffer, Some(project.clone()), cx))),
+                cx,
+            );
+        })?;
+        anyhow::Ok(())
+    })
+    .detach_and_log_err(cx);
+}
+
+pub fn initiate_sign_in(cx: &mut WindowContext) {
+    let Some(cody) = Cody::global(cx) else {
+        return;
+    };
+    let status = cody.read(cx).status();
+    let Some(workspace) = cx.window_handle().downcast::<Workspace>() else {
+        return;
+    };
+    match status {
+        Status::Starting { task } => {
+            let Some(workspace) = cx.window_handle().downcast::<Workspace>() else {
+                return;
+            };
+
+            let Ok(workspace) = workspace.update(cx, |workspace, cx| {
+                workspace.show_toast(
+                    Toast::new(NotificationId::unique::<CodyStartingToast>(), "Cody is starting..."),
+                    cx,
+                );
+                workspace.weak_handle()
+            }) else {
+                return;
+            };
+
+            cx.spawn(|mut cx| async move {
+                task.await;
+                if let Some(cody) = cx.update(|cx| Cody::global(cx)).ok().flatten() {
+                    workspace
+                        .update(&mut cx, |workspace, cx| match cody.read(cx).status() {
+                            Status::Authorized => workspace.show_toast(
+                                Toast::new(
+                                    NotificationId::unique::<CodyStartingToast>(),
+                                    "Cody has started!",
+                                ),
+                                cx,
+                            ),
+                            Status::Error(e) => {
+                                workspace
+                                    .dismiss_toast(&NotificationId::unique::<CodyStartingToast>(), cx);
+                                workspace.show_toast(
+                                    Toast::new(
+                                        NotificationId::unique::<CodyErrorToast>(),
+                                        format!("Cody can't be started: {}", e),
+                                    )
+                                    .on_click("Reinstall Cody", |cx| {
+                                        if let Some(cody) = Cody::global(cx) {
+                                            cody.update(cx, |cody, cx| cody.reinstall(cx))
+                                                .detach();
+                                        }
+                                    }),
+                                    cx,
+                                );
+                            }
+                            _ => {
+                                workspace
+                                    .dismiss_toast(&NotificationId::unique::<CodyStartingToast>(), cx);
+                                cody.update(cx, |cody, cx| cody.sign_in(cx)).detach_and_log_err(cx);
+                            }
+                        })
+                        .log_err();
+                }
+            })
+            .detach();
+        }
+        _ => {
+            // Enterprise instances don't support `SignInInitiate`'s device flow, so skip it
+            // entirely and let the modal prompt for a personal access token instead.
+            if cody.read(cx).current_endpoint() == cody::CODY_AUTH_URL {
+                cody.update(cx, |this, cx| this.sign_in(cx)).detach();
+            }
+            workspace
+                .update(cx, |this, cx| {
+                    this.toggle_modal(cx, |cx| CodyCodeVerification::new(&cody, cx));
+                })
+                .ok();
+        }
+    }
+}
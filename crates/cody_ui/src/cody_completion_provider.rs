@@ -0,0 +1,473 @@
+use anyhow::Result;
+use client::telemetry::Telemetry;
+use cody::{Cody, CodyCompletionDisplay, CodyDebounceMode, CodySettings};
+use editor::{Direction, InlineCompletionProvider};
+use gpui::{AppContext, EntityId, Model, ModelContext, Subscription, Task};
+use language::language_settings::AllLanguageSettings;
+use language::{language_settings::all_language_settings, Anchor, Buffer, OffsetRangeExt, ToOffset};
+use settings::Settings;
+use std::{
+    borrow::Cow,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+pub const CODY_DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(75);
+
+/// In `CodyDebounceMode::Adaptive`, keystrokes closer together than this are considered rapid
+/// typing and get a debounce that grows as the interval shrinks (from `CODY_DEBOUNCE_TIMEOUT`
+/// up to `ADAPTIVE_DEBOUNCE_MAX`); intervals at or beyond this are treated as a pause and get
+/// no debounce at all.
+const ADAPTIVE_DEBOUNCE_RAPID_TYPING_THRESHOLD: Duration = Duration::from_millis(200);
+
+/// The longest an adaptive debounce is ever allowed to grow to, however fast the user is typing.
+const ADAPTIVE_DEBOUNCE_MAX: Duration = Duration::from_millis(300);
+
+pub struct CodyCompletionProvider {
+    cycled: bool,
+    buffer_id: Option<EntityId>,
+    completions: Vec<cody::Completion>,
+    active_completion_index: usize,
+    file_extension: Option<String>,
+    pending_refresh: Task<Result<()>>,
+    pending_cycling_refresh: Task<Result<()>>,
+    /// The cursor position of the most recently issued, not-yet-resolved completion request.
+    /// Used to place a placeholder completion when the agent streams in the first chunk of a
+    /// longer suggestion before the request itself has resolved.
+    pending_cursor_position: Option<Anchor>,
+    /// When the previous debounced `refresh` was requested, used by `CodyDebounceMode::Adaptive`
+    /// to measure the interval between keystrokes.
+    last_keystroke_at: Option<Instant>,
+    /// Whether every other cursor present when the active completion was requested had the same
+    /// text immediately before it on its line as the cursor the request was actually made for,
+    /// making the completion's text safe to insert at all of them. See
+    /// `InlineCompletionProvider::applies_to_all_selections`.
+    applies_to_all_selections: bool,
+    cody: Model<Cody>,
+    telemetry: Option<Arc<Telemetry>>,
+    _cody_subscription: Subscription,
+}
+
+impl CodyCompletionProvider {
+    pub fn new(cody: Model<Cody>, cx: &mut ModelContext<Self>) -> Self {
+        Self {
+            cycled: false,
+            buffer_id: None,
+            completions: Vec::new(),
+            active_completion_index: 0,
+            file_extension: None,
+            pending_refresh: Task::ready(Ok(())),
+            pending_cycling_refresh: Task::ready(Ok(())),
+            pending_cursor_position: None,
+            last_keystroke_at: None,
+            applies_to_all_selections: true,
+            _cody_subscription: cx.subscribe(&cody, Self::handle_cody_event),
+            cody,
+            telemetry: None,
+        }
+    }
+
+    pub fn with_telemetry(mut self, telemetry: Arc<Telemetry>) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
+
+    /// Grows the displayed ghost text as the agent streams in a longer completion: updates the
+    /// matching completion's text in place if one is already displayed, otherwise plants a
+    /// zero-width placeholder at the cursor position of the in-flight request so there's
+    /// something to grow before `refresh`'s own request resolves.
+    fn handle_cody_event(&mut self, _: Model<Cody>, event: &cody::Event, cx: &mut ModelContext<Self>) {
+        let cody::Event::CompletionChunkReceived { uuid, text } = event else {
+            return;
+        };
+        if let Some(completion) = self
+            .completions
+            .iter_mut()
+            .find(|completion| &completion.uuid == uuid)
+        {
+            completion.text = text.clone();
+            cx.notify();
+        } else if let Some(cursor_position) = self.pending_cursor_position {
+            self.completions.push(cody::Completion {
+                uuid: uuid.clone(),
+                range: cursor_position..cursor_position,
+                text: text.clone(),
+                is_snippet: false,
+                requested_at: Instant::now(),
+            });
+            self.active_completion_index = self.completions.len() - 1;
+            cx.notify();
+        }
+    }
+
+    fn active_completion(&self) -> Option<&cody::Completion> {
+        self.completions.get(self.active_completion_index)
+    }
+
+    /// The "N/M, cycle for more" footer appended to the ghost text in
+    /// `CodyCompletionDisplay::Popup` mode, or `None` when there's nothing to cycle through.
+    fn completion_list_footer(&self) -> Option<String> {
+        if self.completions.len() <= 1 {
+            return None;
+        }
+        Some(format!(
+            "-- {}/{} (cycle for more) --",
+            self.active_completion_index + 1,
+            self.completions.len()
+        ))
+    }
+
+    /// Whether the per-language `show_copilot_suggestions` override Cody piggybacks on (as does
+    /// `cody_enabled_for_buffer` in the `cody` crate) permits completions at `cursor_position`, which
+    /// may differ from the buffer's primary language in e.g. Markdown or JSX.
+    fn language_settings_allow_completions(
+        buffer: &Buffer,
+        cursor_position: Anchor,
+        cx: &AppContext,
+    ) -> bool {
+        let file = buffer.file();
+        let language = buffer.language_at(cursor_position);
+        let settings = all_language_settings(file, cx);
+        settings.copilot_enabled(language.as_ref(), file.map(|f| f.path().as_ref()))
+    }
+
+    /// Whether the character just typed before `cursor_position` should trigger a debounced
+    /// completion request, per `cody.trigger_characters`. A language with no entry in that
+    /// setting always triggers, preserving the previous on-every-keystroke behavior; this is
+    /// only consulted for debounced (keystroke-driven) refreshes, never for an explicit
+    /// `cody::Suggest` request.
+    fn last_typed_character_triggers(
+        buffer: &Buffer,
+        cursor_position: Anchor,
+        cx: &AppContext,
+    ) -> bool {
+        let Some(language) = buffer.language_at(cursor_position) else {
+            return true;
+        };
+        let Some(trigger_characters) = CodySettings::get_global(cx)
+            .trigger_characters
+            .get(language.name().as_ref())
+        else {
+            return true;
+        };
+        if trigger_characters.is_empty() {
+            return true;
+        }
+        let offset = cursor_position.to_offset(buffer);
+        let Some(last_char) = buffer.reversed_chars_for_range(0..offset).next() else {
+            return false;
+        };
+        trigger_characters
+            .iter()
+            .any(|trigger| trigger.chars().eq([last_char]))
+    }
+
+    /// Whether `other_cursors` are all completable with the same suggestion as `cursor_position`:
+    /// the agent is only ever asked about `cursor_position`, so its response (e.g. completing an
+    /// identifier or call pattern) is only correct to replay at another cursor if that cursor was
+    /// about to type the same thing -- approximated here by comparing each cursor's line prefix,
+    /// the text already typed since the start of its line.
+    fn selections_share_context(
+        buffer: &Buffer,
+        cursor_position: Anchor,
+        other_cursors: &[Anchor],
+    ) -> bool {
+        let line_prefix = |position: Anchor| {
+            let offset = position.to_offset(buffer);
+            let line_start = language::Point::new(buffer.offset_to_point(offset).row, 0);
+            buffer
+                .text_for_range(buffer.point_to_offset(line_start)..offset)
+                .collect::<String>()
+        };
+        let cursor_line_prefix = line_prefix(cursor_position);
+        other_cursors
+            .iter()
+            .all(|&other_cursor| line_prefix(other_cursor) == cursor_line_prefix)
+    }
+
+    /// How long `refresh` should wait before issuing a completion request for this keystroke.
+    ///
+    /// In `Fixed` mode this is always `CODY_DEBOUNCE_TIMEOUT`. In `Adaptive` mode it grows the
+    /// closer together keystrokes are arriving (up to `ADAPTIVE_DEBOUNCE_MAX`), and drops to
+    /// zero once the user pauses, so a burst of typing doesn't each trigger a wasted request.
+    fn debounce_timeout(&mut self, cx: &AppContext) -> Duration {
+        if CodySettings::get_global(cx).debounce != CodyDebounceMode::Adaptive {
+            return CODY_DEBOUNCE_TIMEOUT;
+        }
+
+        let now = Instant::now();
+        let since_last_keystroke = self.last_keystroke_at.replace(now);
+        match since_last_keystroke.map(|previous| now.saturating_duration_since(previous)) {
+            Some(interval) if interval < ADAPTIVE_DEBOUNCE_RAPID_TYPING_THRESHOLD => {
+                let ratio = ADAPTIVE_DEBOUNCE_RAPID_TYPING_THRESHOLD.as_secs_f32()
+                    / interval.as_secs_f32().max(f32::EPSILON);
+                CODY_DEBOUNCE_TIMEOUT
+                    .mul_f32(ratio)
+                    .min(ADAPTIVE_DEBOUNCE_MAX)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    fn push_completion(&mut self, new_completion: cody::Completion) {
+        for completion in &self.completions {
+            if completion.text == new_completion.text && completion.range == new_completion.range {
+                return;
+            }
+        }
+        self.completions.push(new_completion);
+    }
+}
+
+impl InlineCompletionProvider for CodyCompletionProvider {
+    fn is_enabled(
+        &self,
+        buffer: &Model<Buffer>,
+        cursor_position: language::Anchor,
+        cx: &AppContext,
+    ) -> bool {
+        if !self.cody.read(cx).status().is_authorized() {
+            return false;
+        }
+
+        Self::language_settings_allow_completions(buffer.read(cx), cursor_position, cx)
+    }
+
+    fn refresh(
+        &mut self,
+        buffer: Model<Buffer>,
+        cursor_position: language::Anchor,
+        other_cursors: Vec<language::Anchor>,
+        debounce: bool,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if !Self::language_settings_allow_completions(buffer.read(cx), cursor_position, cx) {
+            self.completions.clear();
+            self.active_completion_index = 0;
+            cx.notify();
+            return;
+        }
+
+        if debounce && !Self::last_typed_character_triggers(buffer.read(cx), cursor_position, cx) {
+            return;
+        }
+
+        self.applies_to_all_selections =
+            Self::selections_share_context(buffer.read(cx), cursor_position, &other_cursors);
+
+        let cody = self.cody.clone();
+        self.pending_cursor_position = Some(cursor_position);
+        let debounce_timeout = debounce.then(|| self.debounce_timeout(cx));
+        // Assigning a new task here drops (and thus cancels) whatever request was still
+        // in flight, so only the most recently requested completion can ever land.
+        self.pending_refresh = cx.spawn(|this, mut cx| async move {
+            if let Some(timeout) = debounce_timeout.filter(|timeout| !timeout.is_zero()) {
+                cx.background_executor().timer(timeout).await;
+            }
+
+            let completions = cody
+                .update(&mut cx, |cody, cx| {
+                    cody.completions(&buffer, cursor_position, cx)
+                })?
+                .await?;
+
+            this.update(&mut cx, |this, cx| {
+                this.pending_cursor_position = None;
+                if !completions.is_empty() {
+                    this.cycled = false;
+                    this.pending_cycling_refresh = Task::ready(Ok(()));
+                    this.completions.clear();
+                    this.active_completion_index = 0;
+                    this.buffer_id = Some(buffer.entity_id());
+                    this.file_extension = buffer.read(cx).file().and_then(|file| {
+                        Some(
+                            Path::new(file.file_name(cx))
+                                .extension()?
+                                .to_str()?
+                                .to_string(),
+                        )
+                    });
+
+                    for completion in completions {
+                        this.push_completion(completion);
+                    }
+                    cx.notify();
+                } else if !this.completions.is_empty() {
+                    // The agent streamed in chunks (planting a placeholder completion) but
+                    // ultimately returned nothing usable for this request; drop the placeholder
+                    // rather than leaving stale ghost text on screen.
+                    this.completions.clear();
+                    this.active_completion_index = 0;
+                    cx.notify();
+                }
+            })?;
+
+            Ok(())
+        });
+    }
+
+    fn cycle(
+        &mut self,
+        buffer: Model<Buffer>,
+        cursor_position: language::Anchor,
+        direction: Direction,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if self.cycled {
+            match direction {
+                Direction::Prev => {
+                    self.active_completion_index = if self.active_completion_index == 0 {
+                        self.completions.len().saturating_sub(1)
+                    } else {
+                        self.active_completion_index - 1
+                    };
+                }
+                Direction::Next => {
+                    if self.completions.len() == 0 {
+                        self.active_completion_index = 0
+                    } else {
+                        self.active_completion_index =
+                            (self.active_completion_index + 1) % self.completions.len();
+                    }
+                }
+            }
+
+            cx.notify();
+        } else {
+            let cody = self.cody.clone();
+            self.pending_cycling_refresh = cx.spawn(|this, mut cx| async move {
+                let completions = cody
+                    .update(&mut cx, |cody, cx| {
+                        cody.completions_cycling(&buffer, cursor_position, cx)
+                    })?
+                    .await?;
+
+                this.update(&mut cx, |this, cx| {
+                    this.cycled = true;
+                    this.buffer_id = Some(buffer.entity_id());
+                    this.file_extension = buffer.read(cx).file().and_then(|file| {
+                        Some(
+                            Path::new(file.file_name(cx))
+                                .extension()?
+                                .to_str()?
+                                .to_string(),
+                        )
+                    });
+                    for completion in completions {
+                        this.push_completion(completion);
+                    }
+                    this.cycle(buffer, cursor_position, direction, cx);
+                })?;
+
+                Ok(())
+            });
+        }
+    }
+
+    fn accept(&mut self, cx: &mut ModelContext<Self>) {
+        if let Some(completion) = self.active_completion() {
+            self.cody
+                .update(cx, |cody, cx| cody.accept_completion(completion, cx))
+                .detach_and_log_err(cx);
+            if let Some(telemetry) = self.telemetry.as_ref() {
+                telemetry.report_copilot_event(
+                    Some(completion.uuid.clone()),
+                    true,
+                    self.file_extension.clone(),
+                );
+            }
+        }
+    }
+
+    fn accept_partial(&mut self, accepted: &str, cx: &mut ModelContext<Self>) {
+        if let Some(completion) = self.active_completion() {
+            // The agent is a Node.js process and indexes strings in UTF-16 code units (like the
+            // `position`s in `GetCompletionsDocument`), not UTF-8 bytes, so a multi-byte prefix
+            // (e.g. "café") must be counted accordingly rather than with `str::len`.
+            let accepted_len = accepted.encode_utf16().count();
+            self.cody
+                .update(cx, |cody, cx| {
+                    cody.accept_partial_completion(completion, accepted_len, cx)
+                })
+                .detach_and_log_err(cx);
+        }
+    }
+
+    fn discard(&mut self, cx: &mut ModelContext<Self>) {
+        let settings = AllLanguageSettings::get_global(cx);
+        if !settings.copilot.feature_enabled {
+            return;
+        }
+
+        self.cody
+            .update(cx, |cody, cx| cody.discard_completions(&self.completions, cx))
+            .detach_and_log_err(cx);
+        if let Some(telemetry) = self.telemetry.as_ref() {
+            telemetry.report_copilot_event(None, false, self.file_extension.clone());
+        }
+    }
+
+    fn active_completion_text(
+        &self,
+        buffer: &Model<Buffer>,
+        cursor_position: language::Anchor,
+        cx: &AppContext,
+    ) -> Option<Cow<'_, str>> {
+        let buffer_id = buffer.entity_id();
+        let buffer = buffer.read(cx);
+        let completion = self.active_completion()?;
+        if Some(buffer_id) != self.buffer_id
+            || !completion.range.start.is_valid(buffer)
+            || !completion.range.end.is_valid(buffer)
+        {
+            return None;
+        }
+
+        let mut completion_range = completion.range.to_offset(buffer);
+        let prefix_len = common_prefix(
+            buffer.chars_for_range(completion_range.clone()),
+            completion.text.chars(),
+        );
+        completion_range.start += prefix_len;
+        let suffix_len = common_prefix(
+            buffer.reversed_chars_for_range(completion_range.clone()),
+            completion.text[prefix_len..].chars().rev(),
+        );
+        completion_range.end = completion_range.end.saturating_sub(suffix_len);
+
+        if !completion_range.is_empty()
+            || completion_range.start != cursor_position.to_offset(buffer)
+        {
+            return None;
+        }
+
+        let completion_text = &completion.text[prefix_len..completion.text.len() - suffix_len];
+        if completion_text.trim().is_empty() {
+            return None;
+        }
+
+        if CodySettings::get_global(cx).completion_display == CodyCompletionDisplay::Popup {
+            if let Some(footer) = self.completion_list_footer() {
+                return Some(Cow::Owned(format!("{completion_text}\n{footer}")));
+            }
+        }
+        Some(Cow::Borrowed(completion_text))
+    }
+
+    fn active_completion_is_snippet(&self, _cx: &AppContext) -> bool {
+        self.active_completion()
+            .map_or(false, |completion| completion.is_snippet)
+    }
+
+    fn applies_to_all_selections(&self, _cx: &AppContext) -> bool {
+        self.applies_to_all_selections
+    }
+}
+
+fn common_prefix<T1: Iterator<Item = char>, T2: Iterator<Item = char>>(a: T1, b: T2) -> usize {
+    a.zip(b)
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum()
+}
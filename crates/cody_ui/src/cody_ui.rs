@@ -0,0 +1,32 @@
+mod chat_panel;
+pub mod cody_button;
+pub mod cody_completion_provider;
+mod edit_panel;
+mod explain_panel;
+mod generate_tests_panel;
+mod log_view;
+mod sign_in;
+
+pub use chat_panel::*;
+pub use cody_button::*;
+pub use cody_completion_provider::*;
+pub use edit_panel::*;
+pub use explain_panel::*;
+pub use generate_tests_panel::*;
+pub use log_view::*;
+pub use sign_in::*;
+
+use gpui::AppContext;
+use workspace::Workspace;
+
+/// Registers `cody::OpenChat` as a workspace-wide action so it shows up in the command palette
+/// (subject to the same `CommandPaletteFilter` visibility as the rest of Cody's actions), in
+/// addition to the existing "Open Chat" entry in the Cody status bar menu.
+pub fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, _| {
+        workspace.register_action(|_workspace, _: &cody::OpenChat, cx| {
+            open_chat(cx);
+        });
+    })
+    .detach();
+}
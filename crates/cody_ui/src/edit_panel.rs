@@ -0,0 +1,106 @@
+use cody::Cody;
+use editor::Editor;
+use gpui::{
+    div, AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView, InteractiveElement,
+    IntoElement, Model, ParentElement, Render, Styled, View, ViewContext,
+};
+use language::{Anchor, Buffer};
+use std::ops::Range;
+use ui::{prelude::*, Button};
+use workspace::ModalView;
+
+pub struct CodyEditPanel {
+    cody: Model<Cody>,
+    buffer: Model<Buffer>,
+    range: Range<Anchor>,
+    instruction_editor: View<Editor>,
+    focus_handle: FocusHandle,
+    pending: bool,
+}
+
+impl FocusableView for CodyEditPanel {
+    fn focus_handle(&self, _: &AppContext) -> gpui::FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl EventEmitter<DismissEvent> for CodyEditPanel {}
+impl ModalView for CodyEditPanel {}
+
+impl CodyEditPanel {
+    pub fn new(
+        cody: Model<Cody>,
+        buffer: Model<Buffer>,
+        range: Range<Anchor>,
+        cx: &mut ViewContext<Self>,
+    ) -> Self {
+        let instruction_editor = cx.new_view(|cx| {
+            let mut editor = Editor::single_line(cx);
+            editor.set_placeholder_text("Tell Cody what to change…", cx);
+            editor
+        });
+        Self {
+            cody,
+            buffer,
+            range,
+            instruction_editor,
+            focus_handle: cx.focus_handle(),
+            pending: false,
+        }
+    }
+
+    fn submit(&mut self, cx: &mut ViewContext<Self>) {
+        if self.pending {
+            return;
+        }
+        let instruction = self
+            .instruction_editor
+            .read(cx)
+            .text(cx)
+            .trim()
+            .to_string();
+        if instruction.is_empty() {
+            return;
+        }
+
+        self.pending = true;
+        let buffer = self.buffer.clone();
+        let range = self.range.clone();
+        let task = self.cody.update(cx, |cody, cx| {
+            cody.edit(&buffer, range.clone(), instruction, cx)
+        });
+        cx.spawn(|this, mut cx| async move {
+            let new_text = task.await;
+            this.update(&mut cx, |this, cx| {
+                this.pending = false;
+                let new_text = new_text?;
+                buffer.update(cx, |buffer, cx| {
+                    buffer.edit([(range, new_text)], None, cx);
+                });
+                cx.emit(DismissEvent);
+                anyhow::Ok(())
+            })?
+        })
+        .detach_and_log_err(cx);
+    }
+}
+
+impl Render for CodyEditPanel {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex()
+            .id("cody-edit-panel")
+            .track_focus(&self.focus_handle)
+            .elevation_3(cx)
+            .w_96()
+            .p_4()
+            .gap_2()
+            .child(Headline::new("Edit with Cody").size(HeadlineSize::Small))
+            .child(div().w_full().child(self.instruction_editor.clone()))
+            .child(
+                Button::new("cody-edit-submit", "Submit")
+                    .full_width()
+                    .disabled(self.pending)
+                    .on_click(cx.listener(|this, _, cx| this.submit(cx))),
+            )
+    }
+}
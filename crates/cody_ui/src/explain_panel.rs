@@ -0,0 +1,85 @@
+use cody::{
+    request::{ChatMessage, ChatSpeaker},
+    Cody,
+};
+use gpui::{
+    div, rems, AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView,
+    InteractiveElement, IntoElement, Model, ParentElement, Render, Styled, View, ViewContext,
+};
+use language::{Anchor, Buffer};
+use std::ops::Range;
+use ui::{prelude::*, Label};
+use workspace::ModalView;
+
+pub struct CodyExplainPanel {
+    messages: Vec<ChatMessage>,
+    pending: bool,
+    focus_handle: FocusHandle,
+}
+
+impl FocusableView for CodyExplainPanel {
+    fn focus_handle(&self, _: &AppContext) -> gpui::FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl EventEmitter<DismissEvent> for CodyExplainPanel {}
+impl ModalView for CodyExplainPanel {}
+
+impl CodyExplainPanel {
+    pub fn new(
+        cody: Model<Cody>,
+        buffer: Model<Buffer>,
+        range: Range<Anchor>,
+        cx: &mut ViewContext<Self>,
+    ) -> Self {
+        let task = cody.update(cx, |cody, cx| cody.explain(&buffer, range, cx));
+        cx.spawn(|this, mut cx| async move {
+            let result = task.await;
+            this.update(&mut cx, |this, cx| {
+                this.pending = false;
+                this.messages = match result {
+                    Ok(messages) => messages,
+                    Err(error) => vec![ChatMessage {
+                        speaker: ChatSpeaker::Assistant,
+                        text: error.to_string(),
+                    }],
+                };
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+
+        Self {
+            messages: Vec::new(),
+            pending: true,
+            focus_handle: cx.focus_handle(),
+        }
+    }
+}
+
+impl Render for CodyExplainPanel {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex()
+            .id("cody-explain-panel")
+            .track_focus(&self.focus_handle)
+            .elevation_3(cx)
+            .w_96()
+            .p_4()
+            .gap_2()
+            .child(Headline::new("Explain with Cody").size(HeadlineSize::Small))
+            .child(if self.pending {
+                div().child(Label::new("Thinking…"))
+            } else {
+                div().child(
+                    v_flex().gap_1().max_h(rems(16.)).children(
+                        self.messages
+                            .iter()
+                            .filter(|message| message.speaker == ChatSpeaker::Assistant)
+                            .map(|message| Label::new(message.text.clone())),
+                    ),
+                )
+            })
+    }
+}
@@ -0,0 +1,158 @@
+use cody::Cody;
+use editor::Editor;
+use gpui::{
+    div, rems, AppContext, DismissEvent, EventEmitter, FocusHandle, FocusableView,
+    InteractiveElement, IntoElement, Model, ParentElement, Render, Styled, View, ViewContext,
+};
+use language::{Anchor, Buffer};
+use project::{Project, ProjectPath, WorktreeId};
+use std::{ops::Range, sync::Arc};
+use ui::{prelude::*, Button, Label};
+use workspace::{ModalView, Workspace};
+
+pub struct CodyGenerateTestsPanel {
+    project: Model<Project>,
+    workspace: View<Workspace>,
+    source_buffer: Model<Buffer>,
+    generated_text: Option<String>,
+    error: Option<String>,
+    pending: bool,
+    inserting: bool,
+    focus_handle: FocusHandle,
+}
+
+impl FocusableView for CodyGenerateTestsPanel {
+    fn focus_handle(&self, _: &AppContext) -> gpui::FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl EventEmitter<DismissEvent> for CodyGenerateTestsPanel {}
+impl ModalView for CodyGenerateTestsPanel {}
+
+impl CodyGenerateTestsPanel {
+    pub fn new(
+        cody: Model<Cody>,
+        project: Model<Project>,
+        workspace: View<Workspace>,
+        buffer: Model<Buffer>,
+        range: Range<Anchor>,
+        cx: &mut ViewContext<Self>,
+    ) -> Self {
+        let task = cody.update(cx, |cody, cx| cody.generate_tests(&buffer, range, cx));
+        cx.spawn(|this, mut cx| async move {
+            let result = task.await;
+            this.update(&mut cx, |this, cx| {
+                this.pending = false;
+                match result {
+                    Ok(text) => this.generated_text = Some(text),
+                    Err(error) => this.error = Some(error.to_string()),
+                }
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+
+        Self {
+            project,
+            workspace,
+            source_buffer: buffer,
+            generated_text: None,
+            error: None,
+            pending: true,
+            inserting: false,
+            focus_handle: cx.focus_handle(),
+        }
+    }
+
+    /// Resolves where the generated test should go: the conventional sibling test file for the
+    /// source buffer's language, created first if it doesn't exist yet, or a new untitled buffer
+    /// when no convention is recognized.
+    fn target_path(&self, cx: &AppContext) -> Option<ProjectPath> {
+        let buffer = self.source_buffer.read(cx);
+        let file = buffer.file()?;
+        let relative_path = cody::test_file_relative_path(buffer)?;
+        Some(ProjectPath {
+            worktree_id: WorktreeId::from_usize(file.worktree_id()),
+            path: Arc::from(relative_path),
+        })
+    }
+
+    fn insert(&mut self, cx: &mut ViewContext<Self>) {
+        if self.inserting {
+            return;
+        }
+        let Some(text) = self.generated_text.clone() else {
+            return;
+        };
+        self.inserting = true;
+
+        let project = self.project.clone();
+        let workspace = self.workspace.clone();
+        let target_path = self.target_path(cx);
+
+        cx.spawn(|this, mut cx| async move {
+            let buffer = if let Some(target_path) = target_path {
+                project
+                    .update(&mut cx, |project, cx| {
+                        project.create_entry(target_path.clone(), false, cx)
+                    })?
+                    .await
+                    .ok();
+                project
+                    .update(&mut cx, |project, cx| project.open_buffer(target_path, cx))?
+                    .await?
+            } else {
+                project.update(&mut cx, |project, cx| project.create_buffer("", None, cx))??
+            };
+
+            buffer.update(&mut cx, |buffer, cx| {
+                let end = buffer.len();
+                buffer.edit([(end..end, format!("\n\n{text}\n"))], None, cx);
+            })?;
+
+            workspace.update(&mut cx, |workspace, cx| {
+                workspace.add_item_to_active_pane(
+                    Box::new(cx.new_view(|cx| Editor::for_buffer(buffer, Some(project.clone()), cx))),
+                    cx,
+                );
+            })?;
+
+            this.update(&mut cx, |_, cx| cx.emit(DismissEvent))?;
+
+            anyhow::Ok(())
+        })
+        .detach_and_log_err(cx);
+    }
+}
+
+impl Render for CodyGenerateTestsPanel {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex()
+            .id("cody-generate-tests-panel")
+            .track_focus(&self.focus_handle)
+            .elevation_3(cx)
+            .w_96()
+            .p_4()
+            .gap_2()
+            .child(Headline::new("Generate Tests with Cody").size(HeadlineSize::Small))
+            .child(if self.pending {
+                div().child(Label::new("Generating tests…"))
+            } else if let Some(error) = &self.error {
+                div().child(Label::new(error.clone()))
+            } else {
+                div().child(
+                    v_flex().gap_1().max_h(rems(16.)).child(Label::new(
+                        self.generated_text.clone().unwrap_or_default(),
+                    )),
+                )
+            })
+            .child(
+                Button::new("cody-generate-tests-insert", "Insert")
+                    .full_width()
+                    .disabled(self.pending || self.error.is_some() || self.inserting)
+                    .on_click(cx.listener(|this, _, cx| this.insert(cx))),
+            )
+    }
+}
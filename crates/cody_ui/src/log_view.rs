@@ -0,0 +1,107 @@
+use cody::{Cody, CodyLogEntry, Event};
+use editor::Editor;
+use gpui::{
+    div, rems, AppContext, ClipboardItem, DismissEvent, EventEmitter, FocusHandle, FocusableView,
+    InteractiveElement, IntoElement, Model, ParentElement, Render, Styled, Subscription, View,
+    ViewContext,
+};
+use ui::{prelude::*, Button, Label};
+use workspace::ModalView;
+
+pub struct CodyLogView {
+    entries: Vec<CodyLogEntry>,
+    filter_editor: View<Editor>,
+    focus_handle: FocusHandle,
+    _subscription: Subscription,
+}
+
+impl FocusableView for CodyLogView {
+    fn focus_handle(&self, _: &AppContext) -> gpui::FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl EventEmitter<DismissEvent> for CodyLogView {}
+impl ModalView for CodyLogView {}
+
+impl CodyLogView {
+    pub fn new(cody: Model<Cody>, cx: &mut ViewContext<Self>) -> Self {
+        let filter_editor = cx.new_view(|cx| {
+            let mut editor = Editor::single_line(cx);
+            editor.set_placeholder_text("Filter logs…", cx);
+            editor
+        });
+        cx.subscribe(&filter_editor, |_, _, _: &editor::EditorEvent, cx| {
+            cx.notify()
+        })
+        .detach();
+
+        Self {
+            entries: cody.read(cx).log_entries().iter().cloned().collect(),
+            _subscription: cx.subscribe(&cody, |this, _, event, cx| {
+                if let Event::LogMessage(entry) = event {
+                    this.entries.push(entry.clone());
+                    cx.notify();
+                }
+            }),
+            filter_editor,
+            focus_handle: cx.focus_handle(),
+        }
+    }
+
+    fn filter_text(&self, cx: &ViewContext<Self>) -> String {
+        self.filter_editor.read(cx).text(cx)
+    }
+
+    fn filtered_entries<'a>(&'a self, filter: &'a str) -> impl Iterator<Item = &'a CodyLogEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| filter.is_empty() || entry.message.contains(filter))
+    }
+
+    fn copy_to_clipboard(&self, cx: &mut ViewContext<Self>) {
+        let filter = self.filter_text(cx);
+        let text = self
+            .filtered_entries(&filter)
+            .map(|entry| format!("[{}] {}", entry.level, entry.message))
+            .collect::<Vec<_>>()
+            .join("\n");
+        cx.write_to_clipboard(ClipboardItem::new(text));
+    }
+}
+
+impl Render for CodyLogView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let filter = self.filter_text(cx);
+        v_flex()
+            .id("cody-log-view")
+            .track_focus(&self.focus_handle)
+            .elevation_3(cx)
+            .w_128()
+            .p_4()
+            .gap_2()
+            .child(Headline::new("Cody Logs").size(HeadlineSize::Small))
+            .child(div().w_full().child(self.filter_editor.clone()))
+            .child(
+                v_flex()
+                    .id("cody-log-entries")
+                    .gap_1()
+                    .h(rems(24.))
+                    .overflow_y_scroll()
+                    .children(self.filtered_entries(&filter).map(|entry| {
+                        Label::new(format!("[{}] {}", entry.level, entry.message))
+                            .size(LabelSize::Small)
+                            .color(match entry.level {
+                                log::Level::Error => Color::Error,
+                                log::Level::Warn => Color::Warning,
+                                _ => Color::Muted,
+                            })
+                    })),
+            )
+            .child(
+                Button::new("cody-log-copy", "Copy to Clipboard")
+                    .full_width()
+                    .on_click(cx.listener(|this, _, cx| this.copy_to_clipboard(cx))),
+            )
+    }
+}
@@ -0,0 +1,311 @@
+use cody::{request::PromptUserDeviceFlow, CancelSignIn, Cody, Status};
+use editor::Editor;
+use gpui::{
+    div, svg, AppContext, ClipboardItem, DismissEvent, Element, EventEmitter, FocusHandle,
+    FocusableView, InteractiveElement, IntoElement, Model, MouseDownEvent, ParentElement, Render,
+    Styled, Subscription, View, ViewContext,
+};
+use ui::{prelude::*, Button, IconName, Label};
+use util::ResultExt;
+use workspace::ModalView;
+
+const CODY_SIGN_UP_URL: &str = "https://sourcegraph.com/cody";
+
+pub struct CodyCodeVerification {
+    status: Status,
+    /// Whether `cody`'s current endpoint is something other than `cody::CODY_AUTH_URL`, meaning
+    /// it doesn't support the `SignInInitiate` device flow and must be signed into with a
+    /// personal access token instead.
+    is_enterprise: bool,
+    connect_clicked: bool,
+    opened_verification_uri: bool,
+    token_editor: View<Editor>,
+    focus_handle: FocusHandle,
+    _subscription: Subscription,
+}
+
+impl FocusableView for CodyCodeVerification {
+    fn focus_handle(&self, _: &AppContext) -> gpui::FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl EventEmitter<DismissEvent> for CodyCodeVerification {}
+impl ModalView for CodyCodeVerification {}
+
+impl CodyCodeVerification {
+    pub(crate) fn new(cody: &Model<Cody>, cx: &mut ViewContext<Self>) -> Self {
+        let status = cody.read(cx).status();
+        let is_enterprise = cody.read(cx).current_endpoint() != cody::CODY_AUTH_URL;
+        let token_editor = cx.new_view(|cx| {
+            let mut editor = Editor::single_line(cx);
+            editor.set_placeholder_text("Paste your Sourcegraph access token", cx);
+            editor
+        });
+        Self {
+            status,
+            is_enterprise,
+            connect_clicked: false,
+            opened_verification_uri: false,
+            token_editor,
+            focus_handle: cx.focus_handle(),
+            _subscription: cx.observe(cody, |this, cody, cx| {
+                let status = cody.read(cx).status();
+                match status {
+                    Status::Authorized | Status::Unauthorized | Status::SigningIn { .. } => {
+                        this.set_status(status, cx)
+                    }
+                    Status::SignedOut if this.is_enterprise => this.set_status(status, cx),
+                    _ => cx.emit(DismissEvent),
+                }
+            }),
+        }
+    }
+
+    pub fn set_status(&mut self, status: Status, cx: &mut ViewContext<Self>) {
+        self.status = status;
+        cx.notify();
+    }
+
+    fn render_device_code(
+        data: &PromptUserDeviceFlow,
+        cx: &mut ViewContext<Self>,
+    ) -> impl IntoElement {
+        let copied = cx
+            .read_from_clipboard()
+            .map(|item| item.text() == &data.user_code)
+            .unwrap_or(false);
+        h_flex()
+            .w_full()
+            .p_1()
+            .border()
+            .border_muted(cx)
+            .rounded_md()
+            .cursor_pointer()
+            .justify_between()
+            .on_mouse_down(gpui::MouseButton::Left, {
+                let user_code = data.user_code.clone();
+                move |_, cx| {
+                    cx.write_to_clipboard(ClipboardItem::new(user_code.clone()));
+                    cx.refresh();
+                }
+            })
+            .child(div().flex_1().child(Label::new(data.user_code.clone())))
+            .child(div().flex_none().px_1().child(Label::new(if copied {
+                "Copied!"
+            } else {
+                "Copy"
+            })))
+    }
+
+    fn render_prompting_modal(
+        &self,
+        connect_clicked: bool,
+        data: &PromptUserDeviceFlow,
+        cx: &mut ViewContext<Self>,
+    ) -> impl Element {
+        let connect_button_label = if connect_clicked {
+            "Waiting for connection..."
+        } else {
+            "Connect to Sourcegraph"
+        };
+        v_flex()
+            .flex_1()
+            .gap_2()
+            .items_center()
+            .child(Headline::new("Use Cody in Zed.").size(HeadlineSize::Large))
+            .child(
+                Label::new("Using Cody requires an active Sourcegraph account.")
+                    .color(Color::Muted),
+            )
+            .child(Self::render_device_code(data, cx))
+            .child(
+                Label::new("Paste this code into Sourcegraph after clicking the button below.")
+                    .size(ui::LabelSize::Small),
+            )
+            .child(
+                Button::new("connect-button", connect_button_label)
+                    .on_click({
+                        let verification_uri = data.verification_uri.clone();
+                        cx.listener(move |this, _, cx| {
+                            cx.open_url(&verification_uri);
+                            this.connect_clicked = true;
+                        })
+                    })
+                    .full_width()
+                    .style(ButtonStyle::Filled),
+            )
+            .child(
+                Button::new("cody-enable-cancel-button", "Cancel")
+                    .full_width()
+                    .on_click(cx.listener(|_, _, cx| {
+                        cx.dispatch_action(CancelSignIn.boxed_clone());
+                        cx.emit(DismissEvent);
+                    })),
+            )
+            .child(self.render_token_sign_in(cx))
+    }
+
+    fn render_token_sign_in(&self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex()
+            .w_full()
+            .gap_2()
+            .pt_2()
+            .border_t_1()
+            .border_color(cx.theme().colors().border_variant)
+            .child(
+                Label::new("Using Enterprise token auth instead?").size(ui::LabelSize::Small),
+            )
+            .child(div().w_full().child(self.token_editor.clone()))
+            .child(
+                Button::new("cody-sign-in-with-token", "Sign In with Token")
+                    .full_width()
+                    .on_click(cx.listener(|this, _, cx| {
+                        let token = this.token_editor.read(cx).text(cx).trim().to_string();
+                        if token.is_empty() {
+                            return;
+                        }
+                        let Some(cody) = Cody::global(cx) else {
+                            return;
+                        };
+                        cody.update(cx, |cody, cx| cody.sign_in_with_token(token, cx))
+                            .detach_and_log_err(cx);
+                    })),
+            )
+    }
+
+    fn render_enabled_modal(cx: &mut ViewContext<Self>) -> impl Element {
+        v_flex()
+            .gap_2()
+            .child(Headline::new("Cody Enabled!").size(HeadlineSize::Large))
+            .child(Label::new(
+                "You can update your settings or sign out from the Cody menu in the status bar.",
+            ))
+            .child(
+                Button::new("cody-enabled-done-button", "Done")
+                    .full_width()
+                    .on_click(cx.listener(|_, _, cx| cx.emit(DismissEvent))),
+            )
+    }
+
+    fn render_unauthorized_modal(cx: &mut ViewContext<Self>) -> impl Element {
+        v_flex()
+            .child(Headline::new("You must have an active Sourcegraph account.").size(HeadlineSize::Large))
+            .child(Label::new(
+                "You can enable Cody by connecting your existing account once you've signed up.",
+            ).color(Color::Warning))
+            .child(
+                Button::new("cody-subscribe-button", "Sign up on Sourcegraph")
+                    .full_width()
+                    .on_click(|_, cx| cx.open_url(CODY_SIGN_UP_URL)),
+            )
+            .child(
+                Button::new("cody-subscribe-cancel-button", "Cancel")
+                    .full_width()
+                    .on_click(cx.listener(|_, _, cx| cx.emit(DismissEvent))),
+            )
+    }
+
+    /// Shown instead of the device-flow prompt when `cody`'s endpoint doesn't support
+    /// `SignInInitiate`, so the user goes straight to token entry rather than through a "Connect
+    /// to Sourcegraph" button that would fail against an Enterprise instance.
+    fn render_enterprise_modal(&self, cx: &mut ViewContext<Self>) -> impl Element {
+        v_flex()
+            .flex_1()
+            .gap_2()
+            .items_center()
+            .child(Headline::new("Use Cody in Zed.").size(HeadlineSize::Large))
+            .child(
+                Label::new("This Sourcegraph instance requires a personal access token.")
+                    .color(Color::Muted),
+            )
+            .child(div().w_full().child(self.token_editor.clone()))
+            .child(
+                Button::new("cody-sign-in-with-token", "Sign In with Token")
+                    .full_width()
+                    .style(ButtonStyle::Filled)
+                    .on_click(cx.listener(|this, _, cx| {
+                        let token = this.token_editor.read(cx).text(cx).trim().to_string();
+                        if token.is_empty() {
+                            return;
+                        }
+                        let Some(cody) = Cody::global(cx) else {
+                            return;
+                        };
+                        cody.update(cx, |cody, cx| cody.sign_in_with_token(token, cx))
+                            .detach_and_log_err(cx);
+                    })),
+            )
+            .child(
+                Button::new("cody-enable-cancel-button", "Cancel")
+                    .full_width()
+                    .on_click(cx.listener(|_, _, cx| cx.emit(DismissEvent))),
+            )
+    }
+
+    fn render_disabled_modal() -> impl Element {
+        v_flex()
+            .child(Headline::new("Cody is disabled").size(HeadlineSize::Large))
+            .child(Label::new("You can enable Cody in your settings."))
+    }
+}
+
+impl Render for CodyCodeVerification {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let prompt = match &self.status {
+            Status::SigningIn {
+                prompt: Some(prompt),
+            } => {
+                if !self.opened_verification_uri {
+                    cx.open_url(&prompt.verification_uri);
+                    self.opened_verification_uri = true;
+                    self.connect_clicked = true;
+                }
+                self.render_prompting_modal(self.connect_clicked, &prompt, cx).into_any_element()
+            }
+            Status::Unauthorized => {
+                self.connect_clicked = false;
+                self.opened_verification_uri = false;
+                Self::render_unauthorized_modal(cx).into_any_element()
+            }
+            Status::Authorized => {
+                self.connect_clicked = false;
+                self.opened_verification_uri = false;
+                Self::render_enabled_modal(cx).into_any_element()
+            }
+            Status::Disabled => {
+                self.connect_clicked = false;
+                self.opened_verification_uri = false;
+                Self::render_disabled_modal().into_any_element()
+            }
+            Status::SignedOut if self.is_enterprise => {
+                self.render_enterprise_modal(cx).into_any_element()
+            }
+            _ => div().into_any_element(),
+        };
+
+        v_flex()
+            .id("cody code verification")
+            .track_focus(&self.focus_handle)
+            .elevation_3(cx)
+            .w_96()
+            .items_center()
+            .p_4()
+            .gap_2()
+            .on_action(cx.listener(|_, _: &menu::Cancel, cx| {
+                cx.emit(DismissEvent);
+            }))
+            .on_any_mouse_down(cx.listener(|this, _: &MouseDownEvent, cx| {
+                cx.focus(&this.focus_handle);
+            }))
+            .child(
+                svg()
+                    .w_32()
+                    .h_16()
+                    .flex_none()
+                    .path(IconName::Cody.path())
+                    .text_color(cx.theme().colors().icon),
+            )
+            .child(prompt)
+    }
+}
@@ -6,7 +6,7 @@ use gpui::{AppContext, EntityId, Model, ModelContext, Task};
 use language::language_settings::AllLanguageSettings;
 use language::{language_settings::all_language_settings, Buffer, OffsetRangeExt, ToOffset};
 use settings::Settings;
-use std::{path::Path, sync::Arc, time::Duration};
+use std::{borrow::Cow, path::Path, sync::Arc, time::Duration};
 
 pub const COPILOT_DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(75);
 
@@ -78,6 +78,7 @@ impl InlineCompletionProvider for CopilotCompletionProvider {
         &mut self,
         buffer: Model<Buffer>,
         cursor_position: language::Anchor,
+        _other_cursors: Vec<language::Anchor>,
         debounce: bool,
         cx: &mut ModelContext<Self>,
     ) {
@@ -215,7 +216,7 @@ impl InlineCompletionProvider for CopilotCompletionProvider {
         buffer: &Model<Buffer>,
         cursor_position: language::Anchor,
         cx: &AppContext,
-    ) -> Option<&str> {
+    ) -> Option<Cow<'_, str>> {
         let buffer_id = buffer.entity_id();
         let buffer = buffer.read(cx);
         let completion = self.active_completion()?;
@@ -245,7 +246,7 @@ impl InlineCompletionProvider for CopilotCompletionProvider {
             if completion_text.trim().is_empty() {
                 None
             } else {
-                Some(completion_text)
+                Some(Cow::Borrowed(completion_text))
             }
         } else {
             None
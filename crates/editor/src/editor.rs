@@ -3946,7 +3946,12 @@ impl Editor {
         None
     }
 
-    fn refresh_inline_completion(
+    /// Asks the active inline completion provider to refresh its completion for the current
+    /// cursor position. Any caching the provider does still applies (e.g. Cody's completion
+    /// cache, which `cody::Suggest` invalidates before calling this). Unlike
+    /// `show_inline_completion`, this requests even when a completion is already being displayed;
+    /// it clears the completion instead when the provider is disabled at the cursor.
+    pub fn refresh_inline_completion(
         &mut self,
         debounce: bool,
         cx: &mut ViewContext<Self>,
@@ -3962,8 +3967,22 @@ impl Editor {
             return None;
         }
 
+        let other_cursors = self
+            .selections
+            .disjoint_anchors()
+            .iter()
+            .filter(|selection| selection.head() != cursor)
+            .filter_map(|selection| {
+                let (selection_buffer, selection_cursor) = self
+                    .buffer
+                    .read(cx)
+                    .text_anchor_for_position(selection.head(), cx)?;
+                (selection_buffer == buffer).then_some(selection_cursor)
+            })
+            .collect();
+
         self.update_visible_inline_completion(cx);
-        provider.refresh(buffer, cursor_buffer_position, debounce, cx);
+        provider.refresh(buffer, cursor_buffer_position, other_cursors, debounce, cx);
         Some(())
     }
 
@@ -4042,16 +4061,39 @@ impl Editor {
     }
 
     fn accept_inline_completion(&mut self, cx: &mut ViewContext<Self>) -> bool {
+        let is_snippet = self
+            .inline_completion_provider()
+            .map_or(false, |provider| provider.active_completion_is_snippet(cx));
+        let applies_to_all_selections = self
+            .inline_completion_provider()
+            .map_or(true, |provider| provider.applies_to_all_selections(cx));
         if let Some(completion) = self.take_active_inline_completion(cx) {
             if let Some(provider) = self.inline_completion_provider() {
                 provider.accept(cx);
             }
 
+            if !applies_to_all_selections && self.selections.count() > 1 {
+                let newest_range = self.selections.newest::<usize>(cx).range();
+                self.change_selections(None, cx, |selections| {
+                    selections.select_ranges([newest_range]);
+                });
+            }
+
+            let completion_text = completion.text.to_string();
             cx.emit(EditorEvent::InputHandled {
                 utf16_range_to_replace: None,
-                text: completion.text.to_string().into(),
+                text: completion_text.clone().into(),
             });
-            self.insert_with_autoindent_mode(&completion.text.to_string(), None, cx);
+
+            let snippet = is_snippet
+                .then(|| Snippet::parse(&completion_text).log_err())
+                .flatten();
+            if let Some(snippet) = snippet {
+                let insertion_range = self.selections.newest::<usize>(cx).range();
+                self.insert_snippet(&[insertion_range], snippet, cx).log_err();
+            } else {
+                self.insert_with_autoindent_mode(&completion_text, None, cx);
+            }
             cx.notify();
             true
         } else {
@@ -4081,6 +4123,10 @@ impl Editor {
                         .collect::<String>();
                 }
 
+                if let Some(provider) = self.inline_completion_provider() {
+                    provider.accept_partial(&partial_completion, cx);
+                }
+
                 cx.emit(EditorEvent::InputHandled {
                     utf16_range_to_replace: None,
                     text: partial_completion.clone().into(),
@@ -4138,7 +4184,7 @@ impl Editor {
                     if let Some(text) =
                         provider.active_completion_text(&buffer, cursor_buffer_position, cx)
                     {
-                        let text = Rope::from(text);
+                        let text = Rope::from(text.as_ref());
                         let mut to_remove = Vec::new();
                         if let Some(completion) = self.active_inline_completion.take() {
                             to_remove.push(completion.id);
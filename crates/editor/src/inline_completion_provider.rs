@@ -1,6 +1,7 @@
 use crate::Direction;
 use gpui::{AppContext, Model, ModelContext};
 use language::Buffer;
+use std::borrow::Cow;
 
 pub trait InlineCompletionProvider: 'static + Sized {
     fn is_enabled(
@@ -9,10 +10,15 @@ pub trait InlineCompletionProvider: 'static + Sized {
         cursor_position: language::Anchor,
         cx: &AppContext,
     ) -> bool;
+    /// `other_cursors` is every other selection's head that also resolves into `buffer`
+    /// (selections in other excerpts/buffers of a multibuffer are omitted), for providers that
+    /// want to decide whether a single completion is representative of every cursor -- see
+    /// `applies_to_all_selections`.
     fn refresh(
         &mut self,
         buffer: Model<Buffer>,
         cursor_position: language::Anchor,
+        other_cursors: Vec<language::Anchor>,
         debounce: bool,
         cx: &mut ModelContext<Self>,
     );
@@ -24,13 +30,35 @@ pub trait InlineCompletionProvider: 'static + Sized {
         cx: &mut ModelContext<Self>,
     );
     fn accept(&mut self, cx: &mut ModelContext<Self>);
+    /// Called when only `accepted` of the active completion's text was committed to the buffer
+    /// (e.g. accepting a single word), rather than the completion in full.
+    fn accept_partial(&mut self, _accepted: &str, _cx: &mut ModelContext<Self>) {}
     fn discard(&mut self, cx: &mut ModelContext<Self>);
+    /// The text to render as ghost text at the cursor. Borrowed (`Cow::Borrowed`) when it's just
+    /// the active completion's own text, or owned (`Cow::Owned`) when a provider composes
+    /// additional text around it, e.g. `CodyCompletionDisplay::Popup`'s numbered listing of every
+    /// available completion.
     fn active_completion_text(
         &self,
         buffer: &Model<Buffer>,
         cursor_position: language::Anchor,
         cx: &AppContext,
-    ) -> Option<&str>;
+    ) -> Option<Cow<'_, str>>;
+    /// Whether the active completion's text is a snippet (e.g. containing `$0`-style tabstops)
+    /// that should be expanded through the editor's snippet machinery on acceptance, rather than
+    /// inserted as plain text.
+    fn active_completion_is_snippet(&self, _cx: &AppContext) -> bool {
+        false
+    }
+    /// Whether, with multiple cursors, the active completion's text is safe to insert at every
+    /// selection (which is how the editor already applies any inline completion when there's
+    /// more than one cursor), rather than only the newest one. Providers that only ever consult
+    /// the newest cursor's surrounding context to produce a completion should return `false` here
+    /// when the other cursors' contexts don't match it closely enough for the same text to make
+    /// sense there too.
+    fn applies_to_all_selections(&self, _cx: &AppContext) -> bool {
+        true
+    }
 }
 
 pub trait InlineCompletionProviderHandle {
@@ -44,6 +72,7 @@ pub trait InlineCompletionProviderHandle {
         &self,
         buffer: Model<Buffer>,
         cursor_position: language::Anchor,
+        other_cursors: Vec<language::Anchor>,
         debounce: bool,
         cx: &mut AppContext,
     );
@@ -55,13 +84,16 @@ pub trait InlineCompletionProviderHandle {
         cx: &mut AppContext,
     );
     fn accept(&self, cx: &mut AppContext);
+    fn accept_partial(&self, accepted: &str, cx: &mut AppContext);
     fn discard(&self, cx: &mut AppContext);
     fn active_completion_text<'a>(
         &self,
         buffer: &Model<Buffer>,
         cursor_position: language::Anchor,
         cx: &'a AppContext,
-    ) -> Option<&'a str>;
+    ) -> Option<Cow<'a, str>>;
+    fn active_completion_is_snippet(&self, cx: &AppContext) -> bool;
+    fn applies_to_all_selections(&self, cx: &AppContext) -> bool;
 }
 
 impl<T> InlineCompletionProviderHandle for Model<T>
@@ -81,11 +113,12 @@ where
         &self,
         buffer: Model<Buffer>,
         cursor_position: language::Anchor,
+        other_cursors: Vec<language::Anchor>,
         debounce: bool,
         cx: &mut AppContext,
     ) {
         self.update(cx, |this, cx| {
-            this.refresh(buffer, cursor_position, debounce, cx)
+            this.refresh(buffer, cursor_position, other_cursors, debounce, cx)
         })
     }
 
@@ -105,6 +138,10 @@ where
         self.update(cx, |this, cx| this.accept(cx))
     }
 
+    fn accept_partial(&self, accepted: &str, cx: &mut AppContext) {
+        self.update(cx, |this, cx| this.accept_partial(accepted, cx))
+    }
+
     fn discard(&self, cx: &mut AppContext) {
         self.update(cx, |this, cx| this.discard(cx))
     }
@@ -114,8 +151,16 @@ where
         buffer: &Model<Buffer>,
         cursor_position: language::Anchor,
         cx: &'a AppContext,
-    ) -> Option<&'a str> {
+    ) -> Option<Cow<'a, str>> {
         self.read(cx)
             .active_completion_text(buffer, cursor_position, cx)
     }
+
+    fn active_completion_is_snippet(&self, cx: &AppContext) -> bool {
+        self.read(cx).active_completion_is_snippet(cx)
+    }
+
+    fn applies_to_all_selections(&self, cx: &AppContext) -> bool {
+        self.read(cx).applies_to_all_selections(cx)
+    }
 }
@@ -1400,6 +1400,15 @@ impl LanguageScope {
         }
     }
 
+    /// Returns the name of the override scope at this location (e.g. `"comment"` or `"string"`,
+    /// as captured by the language's `overrides.scm` query), if any.
+    pub fn override_name(&self) -> Option<&str> {
+        let id = self.override_id?;
+        let grammar = self.language.grammar.as_ref()?;
+        let override_config = grammar.override_config.as_ref()?;
+        override_config.values.get(&id).map(|e| e.0.as_str())
+    }
+
     fn config_override(&self) -> Option<&LanguageConfigOverride> {
         let id = self.override_id?;
         let grammar = self.language.grammar.as_ref()?;
@@ -41,6 +41,7 @@ const JSON_RPC_VERSION: &str = "2.0";
 const CONTENT_LEN_HEADER: &str = "Content-Length: ";
 const LSP_REQUEST_TIMEOUT: Duration = Duration::from_secs(60 * 2);
 const SERVER_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(250);
 
 type NotificationHandler = Box<dyn Send + FnMut(Option<RequestId>, &str, AsyncAppContext)>;
 type ResponseHandler = Box<dyn Send + FnOnce(Result<String, Error>)>;
@@ -69,6 +70,7 @@ pub struct LanguageServer {
     next_id: AtomicI32,
     outbound_tx: channel::Sender<String>,
     name: Arc<str>,
+    version: Option<Arc<str>>,
     capabilities: ServerCapabilities,
     code_action_kinds: Option<Vec<CodeActionKind>>,
     notification_handlers: Arc<Mutex<HashMap<&'static str, NotificationHandler>>>,
@@ -175,6 +177,18 @@ struct AnyNotification<'a> {
 #[derive(Debug, Serialize, Deserialize)]
 struct Error {
     message: String,
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+}
+
+/// Formats a JSON-RPC error for display, folding in any string found in its `data` payload
+/// (where some language servers put the actual human-readable reason, leaving `message` as a
+/// generic summary like "Internal error").
+fn format_error_message(error: Error) -> String {
+    match error.data.as_ref().and_then(|data| data.as_str()) {
+        Some(reason) => format!("{}: {reason}", error.message),
+        None => error.message,
+    }
 }
 
 pub trait LspRequestFuture<O>: Future<Output = O> {
@@ -380,6 +394,7 @@ impl LanguageServer {
             response_handlers,
             io_handlers,
             name: "".into(),
+            version: None,
             capabilities: Default::default(),
             code_action_kinds,
             next_id: Default::default(),
@@ -713,6 +728,7 @@ impl LanguageServer {
             let response = self.request::<request::Initialize>(params).await?;
             if let Some(info) = response.server_info {
                 self.name = info.name.into();
+                self.version = info.version.map(Into::into);
             }
             self.capabilities = response.capabilities;
 
@@ -772,6 +788,37 @@ impl LanguageServer {
         }
     }
 
+    /// Polls the underlying process until it exits on its own, then calls `on_exit`. Stops
+    /// without firing once [`Self::shutdown`] has taken the [`Child`] out of `self.server`; a
+    /// process that exits in response to shutdown's `exit` notification before that point is
+    /// still reported, so this alone does not tell a crash from a graceful shutdown. Holds only a
+    /// [`Weak`] reference to `self.server`, so it quietly stops (without calling `on_exit`) if
+    /// `self` is dropped first, e.g. because it was replaced by a fresh `LanguageServer` from a
+    /// restart. For a `LanguageServer` built without an underlying child process (e.g.
+    /// `FakeLanguageServer` in tests), the returned task resolves immediately without calling
+    /// `on_exit`.
+    pub fn on_exit(&self, on_exit: impl FnOnce() + 'static + Send) -> Task<()> {
+        let server = Arc::downgrade(&self.server);
+        let executor = self.executor.clone();
+        executor.clone().spawn(async move {
+            loop {
+                let Some(server) = server.upgrade() else {
+                    return;
+                };
+                let exited = match server.lock().as_mut() {
+                    None => return,
+                    Some(child) => !matches!(child.try_wait(), Ok(None)),
+                };
+                drop(server);
+                if exited {
+                    on_exit();
+                    return;
+                }
+                executor.timer(EXIT_POLL_INTERVAL).await;
+            }
+        })
+    }
+
     /// Register a handler to handle incoming LSP notifications.
     ///
     /// [LSP Specification](https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#notificationMessage)
@@ -882,6 +929,7 @@ impl LanguageServer {
                                                 id,
                                                 value: LspResult::Error(Some(Error {
                                                     message: error.to_string(),
+                                                    data: None,
                                                 })),
                                             },
                                         };
@@ -908,6 +956,7 @@ impl LanguageServer {
                                 result: None,
                                 error: Some(Error {
                                     message: error.to_string(),
+                                    data: None,
                                 }),
                             };
                             if let Some(response) = serde_json::to_string(&response).log_err() {
@@ -933,6 +982,12 @@ impl LanguageServer {
         &self.name
     }
 
+    /// Get the version the running language server reported in its `initialize` response's
+    /// `server_info`, if any.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
     /// Get the reported capabilities of the running language server.
     pub fn capabilities(&self) -> &ServerCapabilities {
         &self.capabilities
@@ -1006,7 +1061,7 @@ impl LanguageServer {
                                             Err(error).context("failed to deserialize response")
                                         }
                                     }
-                                    Err(error) => Err(anyhow!("{}", error.message)),
+                                    Err(error) => Err(anyhow!("{}", format_error_message(error))),
                                 };
                                 _ = tx.send(response);
                             })
@@ -51,6 +51,10 @@ pub enum IconName {
     Collab,
     Command,
     Control,
+    Cody,
+    CodyDisabled,
+    CodyError,
+    CodyInit,
     Copilot,
     CopilotDisabled,
     CopilotError,
@@ -151,6 +155,10 @@ impl IconName {
             IconName::Collab => "icons/user_group_16.svg",
             IconName::Command => "icons/command.svg",
             IconName::Control => "icons/control.svg",
+            IconName::Cody => "icons/cody.svg",
+            IconName::CodyDisabled => "icons/cody_disabled.svg",
+            IconName::CodyError => "icons/cody_error.svg",
+            IconName::CodyInit => "icons/cody_init.svg",
             IconName::Copilot => "icons/copilot.svg",
             IconName::CopilotDisabled => "icons/copilot_disabled.svg",
             IconName::CopilotError => "icons/copilot_error.svg",
@@ -115,6 +115,31 @@ pub trait HttpClient: Send + Sync {
         }
     }
 
+    /// Like `get`, but with extra request headers, e.g. `Range` to resume an interrupted
+    /// download.
+    fn get_with_headers<'a>(
+        &'a self,
+        uri: &str,
+        headers: &[(&str, &str)],
+        follow_redirects: bool,
+    ) -> BoxFuture<'a, Result<Response<AsyncBody>, Error>> {
+        let mut request = isahc::Request::builder()
+            .redirect_policy(if follow_redirects {
+                RedirectPolicy::Follow
+            } else {
+                RedirectPolicy::None
+            })
+            .method(Method::GET)
+            .uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        match request.body(AsyncBody::empty()) {
+            Ok(request) => self.send(request),
+            Err(error) => async move { Err(error.into()) }.boxed(),
+        }
+    }
+
     fn post_json<'a>(
         &'a self,
         uri: &str,
@@ -12,6 +12,8 @@ use clap::{command, Parser};
 use cli::FORCE_CLI_MODE_ENV_VAR_NAME;
 use client::{parse_zed_link, telemetry::Telemetry, Client, DevServerToken, UserStore};
 use collab_ui::channel_view::ChannelView;
+use cody::Cody;
+use cody_ui::{CodyCompletionProvider, CodyEditPanel, CodyExplainPanel, CodyGenerateTestsPanel};
 use copilot::Copilot;
 use copilot_ui::CopilotCompletionProvider;
 use db::kvp::KEY_VALUE_STORE;
@@ -24,7 +26,7 @@ use gpui::{
 };
 use image_viewer;
 use isahc::{prelude::Configurable, Request};
-use language::LanguageRegistry;
+use language::{LanguageRegistry, OffsetRangeExt, ToPoint};
 use log::LevelFilter;
 
 use assets::Assets;
@@ -275,6 +277,9 @@ fn init_ui(args: Args) {
             node_runtime.clone(),
             cx,
         );
+        let cody_language_server_id = languages.next_language_server_id();
+        cody::init(cody_language_server_id, http.clone(), node_runtime.clone(), cx);
+        cody_ui::init(cx);
         assistant::init(client.clone(), cx);
         init_inline_completion_provider(client.telemetry().clone(), cx);
 
@@ -1224,4 +1229,139 @@ fn init_inline_completion_provider(telemetry: Arc<Telemetry>, cx: &mut AppContex
         })
         .detach();
     }
+
+    if let Some(cody) = Cody::global(cx) {
+        cx.observe_new_views(move |editor: &mut Editor, cx: &mut ViewContext<Editor>| {
+            if editor.mode() == EditorMode::Full {
+                editor
+                    .register_action(cx.listener({
+                        let cody = cody.clone();
+                        move |editor, _: &cody::Suggest, cx: &mut ViewContext<Editor>| {
+                            if let Some(buffer) = editor.buffer().read(cx).as_singleton() {
+                                cody.update(cx, |cody, _| {
+                                    cody.invalidate_completion_cache(&buffer);
+                                });
+                            }
+                            editor.refresh_inline_completion(false, cx);
+                        }
+                    }))
+                    .register_action(cx.listener({
+                        let cody = cody.clone();
+                        move |editor, _: &cody::ToggleCodyForBuffer, cx: &mut ViewContext<Editor>| {
+                            if let Some(buffer) = editor.buffer().read(cx).as_singleton() {
+                                cody.update(cx, |cody, cx| {
+                                    cody.toggle_muted_for_buffer(&buffer, cx);
+                                });
+                            }
+                        }
+                    }))
+                    .register_action(cx.listener(
+                        |editor, _: &cody::NextSuggestion, cx: &mut ViewContext<Editor>| {
+                            editor.next_inline_completion(&Default::default(), cx);
+                        },
+                    ))
+                    .register_action(cx.listener(
+                        |editor, _: &cody::PreviousSuggestion, cx: &mut ViewContext<Editor>| {
+                            editor.previous_inline_completion(&Default::default(), cx);
+                        },
+                    ))
+                    .register_action(cx.listener({
+                        let cody = cody.clone();
+                        move |editor, _: &cody::EditSelection, cx: &mut ViewContext<Editor>| {
+                            let Some(buffer) = editor.buffer().read(cx).as_singleton() else {
+                                return;
+                            };
+                            let range = editor.selections.newest_anchor().range();
+                            let range = range.start.text_anchor..range.end.text_anchor;
+                            let Some(workspace) = editor.workspace() else {
+                                return;
+                            };
+                            let cody = cody.clone();
+                            workspace.update(cx, |workspace, cx| {
+                                workspace.toggle_modal(cx, |cx| {
+                                    CodyEditPanel::new(cody, buffer, range, cx)
+                                });
+                            });
+                        }
+                    }))
+                    .register_action(cx.listener({
+                        let cody = cody.clone();
+                        move |editor, _: &cody::ExplainSelection, cx: &mut ViewContext<Editor>| {
+                            let Some(buffer) = editor.buffer().read(cx).as_singleton() else {
+                                return;
+                            };
+                            let range = editor.selections.newest_anchor().range();
+                            if range.start == range.end {
+                                return;
+                            }
+                            let range = range.start.text_anchor..range.end.text_anchor;
+                            let Some(workspace) = editor.workspace() else {
+                                return;
+                            };
+                            let cody = cody.clone();
+                            workspace.update(cx, |workspace, cx| {
+                                workspace.toggle_modal(cx, |cx| {
+                                    CodyExplainPanel::new(cody, buffer, range, cx)
+                                });
+                            });
+                        }
+                    }))
+                    .register_action(cx.listener({
+                        let cody = cody.clone();
+                        move |editor, _: &cody::GenerateTests, cx: &mut ViewContext<Editor>| {
+                            let Some(buffer) = editor.buffer().read(cx).as_singleton() else {
+                                return;
+                            };
+                            let selection_range = editor.selections.newest_anchor().range();
+                            let range = if selection_range.start == selection_range.end {
+                                let snapshot = buffer.read(cx).snapshot();
+                                let point = selection_range.start.text_anchor.to_point(&snapshot);
+                                let Some(enclosing_function) = snapshot
+                                    .outline(None)
+                                    .and_then(|outline| {
+                                        outline
+                                            .items
+                                            .into_iter()
+                                            .filter(|item| {
+                                                let item_range = item.range.to_point(&snapshot);
+                                                item_range.start <= point && point <= item_range.end
+                                            })
+                                            .max_by_key(|item| item.depth)
+                                    })
+                                else {
+                                    return;
+                                };
+                                enclosing_function.range
+                            } else {
+                                selection_range.start.text_anchor..selection_range.end.text_anchor
+                            };
+                            let Some(workspace) = editor.workspace() else {
+                                return;
+                            };
+                            let project = workspace.read(cx).project().clone();
+                            let cody = cody.clone();
+                            workspace.update(cx, |workspace, cx| {
+                                let workspace_handle = cx.view().clone();
+                                workspace.toggle_modal(cx, |cx| {
+                                    CodyGenerateTestsPanel::new(
+                                        cody,
+                                        project,
+                                        workspace_handle,
+                                        buffer,
+                                        range,
+                                        cx,
+                                    )
+                                });
+                            });
+                        }
+                    }));
+
+                let provider = cx.new_model(|cx| {
+                    CodyCompletionProvider::new(cody.clone(), cx).with_telemetry(telemetry.clone())
+                });
+                editor.set_inline_completion_provider(provider, cx)
+            }
+        })
+        .detach();
+    }
 }